@@ -0,0 +1,83 @@
+use std::{fmt, net::IpAddr, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+// A network like 10.0.0.0/8, a single address is a network of its own
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        // Ipv4 clients of an ipv6 listener show up as mapped addresses
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let shift = 32 - self.prefix as u32;
+                u32::from(network).checked_shr(shift).unwrap_or(0) == u32::from(ip).checked_shr(shift).unwrap_or(0)
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let shift = 128 - self.prefix as u32;
+                u128::from(network).checked_shr(shift).unwrap_or(0) == u128::from(ip).checked_shr(shift).unwrap_or(0)
+            }
+            _ => false,
+        }
+    }
+}
+
+// Denied networks win over allowed ones, without allowed networks every address is allowed
+pub fn is_allowed(allow: &[Cidr], deny: &[Cidr], ip: &IpAddr) -> bool {
+    if deny.iter().any(|cidr| cidr.contains(ip)) {
+        return false;
+    }
+    allow.is_empty() || allow.iter().any(|cidr| cidr.contains(ip))
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match string.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (string, None),
+        };
+
+        let network: IpAddr = match address.trim().parse() {
+            Ok(network) => network,
+            Err(_) => return Err(format!("Invalid ip address in {}", string)),
+        };
+
+        let max: u8 = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => match prefix.trim().parse::<u8>() {
+                Ok(prefix) if prefix <= max => prefix,
+                _ => return Err(format!("Invalid prefix length in {}", string)),
+            },
+            None => max,
+        };
+
+        Ok(Cidr { network, prefix })
+    }
+}
+
+impl TryFrom<String> for Cidr {
+    type Error = String;
+
+    fn try_from(string: String) -> Result<Self, Self::Error> {
+        string.parse()
+    }
+}
+
+impl From<Cidr> for String {
+    fn from(cidr: Cidr) -> Self {
+        cidr.to_string()
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
@@ -5,29 +5,34 @@ use std::{
     time::Duration,
 };
 
-use evmap::{ReadHandle, WriteHandle};
+use evmap::ReadHandle;
 
 use crate::{
     connection::{
         connection_state::ConnectionState,
         handle_instruction::handle_instruction,
         handle_operation::handle_operation,
-        read_all_from_stream::{read_all_from_stream, TcpError},
+        read_all_from_stream::{MessageReader, TcpError},
     },
+    dbop::{kernel::Kernel, response::Response},
+    error::Error,
     lexer::{
         lex::lex,
-        token::KeywordType,
+        token::{Keyword, KeywordType},
         validate::{is_valid, Part},
     },
 };
 
-use super::permission::Permission;
+use crate::Mode;
+
+use super::permission_list::SharedPermissions;
 
 pub const END_OF_MESSAGE: char = ''; // use end of text
 pub const BUFFER_SIZE: usize = 1024;
 pub const MAX_MESSAGE_LENGTH: usize = 2048;
 pub const MAX_SOCKET_BUFFER_LENGTH: usize = 8192;
-pub const ERR_PREFIX: &str = "Err: ";
+// Default limit for length prefixed frames, see --max-frame-length
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 16 * 1024 * 1024;
 
 // Timeout time for read after first chunk of data was sent
 pub const READ_TIME_OUT: Duration = Duration::new(10, 0);
@@ -35,20 +40,48 @@ pub const READ_TIME_OUT: Duration = Duration::new(10, 0);
 // Timeout time since last full message sent
 pub const IDLE_TIME_OUT: Option<Duration> = Some(Duration::new(20, 0));
 
+// Set on startup, the same for every connection
+#[derive(Debug, Clone)]
+pub struct ConnectionSettings {
+    pub mode: Mode,
+    // Connections have to AUTH before anything else, otherwise they are closed
+    pub require_auth: bool,
+    // Connections whose token is gone after the permissions were reloaded are closed
+    pub disconnect_revoked: bool,
+    pub max_frame_length: usize,
+}
+
+// Writes the whole response, framed like the requests of the connection
+fn respond(stream: &mut TcpStream, reader: &MessageReader, response: &str) -> std::io::Result<()> {
+    stream.write_all(&reader.framing().frame(response))?;
+    stream.flush()
+}
+
 pub fn handle_connection(
     mut stream: TcpStream,
     read_handle: ReadHandle<String, String>,
-    write_mutex: Arc<Mutex<WriteHandle<String, String>>>,
-    permission: Permission,
+    expiry_handle: ReadHandle<String, i64>,
+    write_mutex: Arc<Mutex<Kernel>>,
+    permissions: Arc<SharedPermissions>,
+    settings: ConnectionSettings,
 ) {
-    let mut connection_state = ConnectionState::new(read_handle, write_mutex);
+    // The address is gone once the client disconnected, so it is read once
+    let (peer, ip) = match stream.peer_addr() {
+        Ok(address) => (address.to_string(), address.ip().to_string()),
+        Err(_) => ("unknown".to_string(), "unknown".to_string()),
+    };
+
+    // Connections start with the default permission until they AUTH
+    let mut permission = permissions.read().default_permission(&settings.mode);
+    let mut connection_state = ConnectionState::new(read_handle, expiry_handle, write_mutex, permissions, ip);
+    let mut reader = MessageReader::new(settings.max_frame_length);
 
     loop {
         // Continue means a recoverable error was transmitted
         // Return means an irrecoverable error was transmitted
         let message: String;
 
-        match read_all_from_stream(&mut stream) {
+        match reader.read_message(&stream) {
             Ok(msg) => {
                 // Reset the read timeout for the socket
                 let _ = stream.set_read_timeout(IDLE_TIME_OUT);
@@ -58,156 +91,118 @@ pub fn handle_connection(
                 let _ = stream.set_read_timeout(IDLE_TIME_OUT);
 
                 // Continue if the error is recoverable else shutdown the Connection
-                match &err {
+                let response: Option<Error> = match &err {
                     // Message exceeds MAX_MESSAGE_LENGTH but is not considered as spam / overloading the db
                     TcpError::MessageTooLong(bytes) => {
-                        let _ = stream.write(
-                            format!(
-                                "{}Message was longer than allowed {} bytes, ({})",
-                                ERR_PREFIX, MAX_MESSAGE_LENGTH, bytes
-                            )
-                            .as_bytes(),
-                        );
-                        continue;
+                        Some(Error::MessageTooLong { length: *bytes, max: MAX_MESSAGE_LENGTH })
                     }
 
                     // Message exceeds the max buffer length and is seen as deliberate spam, thus connection is closed
-                    TcpError::MessageExceedsMaxLength() => {
-                        let _ = stream.write(
-                            format!(
-                                "{}Message exceeded max length {}, Connection closed.",
-                                ERR_PREFIX, MAX_SOCKET_BUFFER_LENGTH
-                            )
-                            .as_bytes(),
-                        );
-                    }
-
-                    // Message has multiple end_of_message bytes
-                    TcpError::MessagePolluted(_pollution) => {
-                        //
-                        let _ = stream.write(
-                            format!(
-                                "{}Message is Polluted. Polluted Data: '{}'",
-                                ERR_PREFIX, _pollution
-                            )
-                            .as_bytes(),
-                        );
-                        continue;
-                    }
+                    TcpError::MessageExceedsMaxLength(max) => Some(Error::MessageExceedsMaxLength { max: *max }),
 
                     // Invalid Utf8 for message
-                    TcpError::MessageUtf8Error() => {
-                        let _ = stream.write(
-                            format!("{}Invalid Utf8, Connection closed.", ERR_PREFIX).as_bytes(),
-                        );
-                    }
+                    TcpError::MessageUtf8Error() => Some(Error::InvalidUtf8),
+
                     //
-                    TcpError::TcpShutdown() => {}
-                    TcpError::TcpShutdownFromClient() => {}
+                    TcpError::TcpShutdown() => None,
+                    TcpError::TcpShutdownFromClient() => None,
 
                     // Tcp Socket Timed out
-                    TcpError::TcpTimeout() => {
-                        // Maybe log the ip for it
-                        let _ = stream.write(
-                            format!(
-                                "{}Connection timed out- Connection closed. Read_timeout:{:?}, Idle_timeout:{:#?}",
-                                ERR_PREFIX, READ_TIME_OUT, IDLE_TIME_OUT
-                            )
-                            .as_bytes(),
-                        );
-                    }
+                    TcpError::TcpTimeout() => Some(Error::Timeout(format!(
+                        "Connection timed out- Connection closed. Read_timeout:{:?}, Idle_timeout:{:#?}",
+                        READ_TIME_OUT, IDLE_TIME_OUT
+                    ))),
 
                     // Every other error
-                    TcpError::TcpUnkownError(_string) => {}
+                    TcpError::TcpUnkownError(_string) => None,
+                };
+
+                // The whole message was read, so only this message is rejected and the connection stays open
+                if let (TcpError::MessageTooLong(_) | TcpError::MessageUtf8Error(), Some(response)) = (&err, &response) {
+                    match respond(&mut stream, &reader, &connection_state.format.encode_error(response)) {
+                        Ok(()) => continue,
+                        Err(write_err) => {
+                            println!("{}: Closing connection due to failed write {:?}", peer, write_err);
+                            let _ = stream.shutdown(std::net::Shutdown::Both);
+                            return;
+                        }
+                    }
+                }
+
+                // The connection is closed either way, so a failed write is not reported
+                if let Some(response) = response {
+                    let _ = respond(&mut stream, &reader, &connection_state.format.encode_error(&response));
                 }
 
                 // Irrecoverable Error
-                println!(
-                    "{:?}: Closing connection due to {:?}",
-                    stream.peer_addr().unwrap(),
-                    err
-                );
+                println!("{}: Closing connection due to {:?}", peer, err);
                 let _ = stream.shutdown(std::net::Shutdown::Both);
                 return;
             }
         }
 
-        //
-        let _ = stream.set_read_timeout(IDLE_TIME_OUT);
-
         if message == String::from("QUIT") {
             break;
         }
 
+        // The permission file may have been reloaded since the last message
+        if let Err(err) = connection_state.refresh_permission(&mut permission, settings.disconnect_revoked) {
+            let _ = respond(&mut stream, &reader, &connection_state.format.encode_error(&err));
+            println!("{}: Closing connection due to revoked token", peer);
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+            return;
+        }
+
         // Lex the input string into tokens
         let tokens = lex(message);
 
         // Check through StateMachine if the given tokens are in a valid construct
         // If they are it loads them into parts and continues
-        let parts: Vec<Part>;
-
-        match is_valid(tokens) {
-            Ok(validated_parts) => {
-                parts = validated_parts;
+        let result: Result<Response, Error> = match is_valid(tokens) {
+            Ok(parts) if settings.require_auth && !connection_state.authenticated && !is_auth(&parts) => {
+                Err(Error::PermissionDenied("Authenticate with AUTH first, Connection closed.".to_string()))
             }
-            Err(err) => {
-                if err.len() == 0 {
-                    let _ = stream.write(format!("{}Unidentified Error", ERR_PREFIX).as_bytes());
-                } else {
-                    let _ = stream.write(format!("{}{}", ERR_PREFIX, err).as_bytes());
-                }
-                continue;
-            }
-        }
-
-        if parts.len() == 0 {
-            let _ = stream.write(format!("{} Empty Command", ERR_PREFIX).as_bytes());
+            // Operation vs Instruction
+            Ok(parts) => match parts.first().unwrap() {
+                Part::Keyword {
+                    keyword: _,
+                    keyword_type: KeywordType::Operation,
+                } => handle_operation(parts, &mut connection_state, &permission),
+                Part::Keyword {
+                    keyword: _,
+                    keyword_type: KeywordType::Instruction,
+                } => handle_instruction(parts, &mut connection_state, &mut permission),
+                _ => Err(Error::InvalidCommand("Command has to start with a Keyword".to_string())),
+            },
+            Err(err) => Err(err),
+        };
+
+        // Encoded after handling, FORMAT changes the format of its own response
+        let response = match result {
+            Ok(ok) => connection_state.format.encode(&ok),
+            Err(err) => connection_state.format.encode_error(&err),
+        };
+
+        // A response that can not be written leaves the client out of sync, so the connection is closed
+        if let Err(err) = respond(&mut stream, &reader, &response) {
+            println!("{}: Closing connection due to failed write {:?}", peer, err);
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+            return;
         }
-        
-        // Check permissions
-
-        // Have meta operations here too
-        // Operation vs Instruction
-        match parts.first().unwrap() {
-            Part::Keyword {
-                keyword: _,
-                keyword_type,
-            } => {
-                match keyword_type {
-                    KeywordType::Operation => {
-                        match handle_operation(parts, &mut connection_state, &permission) {
-                            Ok(ok) => {
-                                let _ = stream.write(ok.as_bytes());
-                            }
-                            Err(err) => {
-                                let _ = stream.write(format!("{}{}", ERR_PREFIX, err).as_bytes());
-                                continue;
-                            }
-                        }
-                    }
-                    KeywordType::Instruction => {
-                        match handle_instruction(parts, &mut connection_state, &permission) {
-                            Ok(ok) => {
-                                let _ = stream.write(ok.as_bytes());
-                            }
-                            Err(err) => {
-                                let _ = stream.write(format!("{}{}", ERR_PREFIX, err).as_bytes());
-                                continue;
-                            }
-                        }
-                    }
-                };
-            }
 
-            _ => {
-                let _ = stream
-                    .write(format!("{}This Error should never be thrown. But fuck it lets have it here anyway.", ERR_PREFIX).as_bytes());
-                continue;
-            }
+        // Anything but a successful AUTH closes the connection when authentication is required
+        if settings.require_auth && !connection_state.authenticated {
+            println!("{}: Closing connection due to missing authentication", peer);
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+            return;
         }
     }
 
     // This is only for correct termination
-    println!("Closing connection: {:?}", stream.peer_addr().unwrap());
+    println!("Closing connection: {}", peer);
     let _ = stream.shutdown(std::net::Shutdown::Both);
 }
+
+fn is_auth(parts: &[Part]) -> bool {
+    matches!(parts.first(), Some(Part::Keyword { keyword: Keyword::AUTH, .. }))
+}
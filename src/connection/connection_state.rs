@@ -1,7 +1,21 @@
-use std::sync::{Arc, Mutex};
+use std::collections::HashSet;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
 
-use crate::dbop::op_type::Op;
-use evmap::{ReadHandle, WriteHandle};
+use crate::{
+    dbop::{kernel::Kernel, op_type::Op, origin::Origin, response::ResponseFormat},
+    lexer::validate::Lifetime,
+    error::Error,
+    persistence::record::Record,
+};
+use evmap::ReadHandle;
+
+use super::{permission::Permission, permission_list::SharedPermissions};
+
+// Source of the ids that connection lifetimes are bound to
+static NEXT_CONNECTION_ID: AtomicUsize = AtomicUsize::new(0);
 
 #[derive(Debug)]
 pub enum OperationMode {
@@ -10,14 +24,125 @@ pub enum OperationMode {
 }
 
 pub struct ConnectionState {
+    pub id: String,
     pub mode: OperationMode,
+    // Lifetime for PUT operations without an explicit lifetime, set by WITH
+    pub default_lifetime: Option<Lifetime>,
+    // Linked groups this connection is subscribed to
+    pub linked: HashSet<String>,
+    // Encoding of the responses, set by FORMAT
+    pub format: ResponseFormat,
     pub op_queue: Vec<Op>,
     pub read_handle: ReadHandle<String, String>,
-    pub write_mutex: Arc<Mutex<WriteHandle<String, String>>>,
+    pub expiry_handle: ReadHandle<String, i64>,
+    pub write_mutex: Arc<Mutex<Kernel>>,
+    // Tokens that AUTH is checked against
+    pub permissions: Arc<SharedPermissions>,
+    // Ip of the client
+    pub address: String,
+    // Set by a successful AUTH
+    pub authenticated: bool,
+    // Token of the last successful AUTH
+    pub token: Option<String>,
+    // Generation of the permissions the permission of the connection was evaluated with
+    pub generation: usize,
 }
 
 impl ConnectionState {
-    pub fn new(read_handle: ReadHandle<String, String>, write_mutex: Arc<Mutex<WriteHandle<String, String>>>) -> Self {
-        ConnectionState { mode: OperationMode::Default, op_queue: Vec::new(), read_handle: read_handle, write_mutex: write_mutex }
+    pub fn new(
+        read_handle: ReadHandle<String, String>,
+        expiry_handle: ReadHandle<String, i64>,
+        write_mutex: Arc<Mutex<Kernel>>,
+        permissions: Arc<SharedPermissions>,
+        address: String,
+    ) -> Self {
+        let id = NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed).to_string();
+        let generation = permissions.generation();
+        ConnectionState {
+            id,
+            mode: OperationMode::Default,
+            default_lifetime: None,
+            linked: HashSet::new(),
+            format: ResponseFormat::Legacy,
+            op_queue: Vec::new(),
+            read_handle,
+            expiry_handle,
+            write_mutex,
+            permissions,
+            address,
+            authenticated: false,
+            token: None,
+            generation,
+        }
+    }
+
+    // Evaluates the token of the connection again after the permissions were reloaded
+    // A revoked token falls back to the default permission and has to AUTH again, unless revoked connections are closed
+    pub fn refresh_permission(&mut self, permission: &mut Permission, disconnect_revoked: bool) -> Result<(), Error> {
+        let generation = self.permissions.generation();
+        if generation == self.generation {
+            return Ok(());
+        }
+        self.generation = generation;
+
+        let list = self.permissions.read();
+        let refreshed = match &self.token {
+            Some(token) => list.permission_from_auth_str(token.clone(), self.address.clone(), permission.mode()),
+            None => Some(list.default_permission(permission.mode())),
+        };
+
+        match refreshed {
+            Some(refreshed) => *permission = refreshed,
+            None if disconnect_revoked => {
+                return Err(Error::PermissionDenied("Token was revoked, Connection closed.".to_string()));
+            }
+            None => {
+                *permission = list.default_permission(permission.mode());
+                self.authenticated = false;
+                self.token = None;
+            }
+        }
+        Ok(())
+    }
+
+    // Subscribes the connection to the linked group of the lifetime
+    pub fn attach(&mut self, lifetime: &Lifetime) {
+        if let Lifetime::Linked(group) = lifetime.root() {
+            if self.linked.insert(group.clone()) {
+                self.write_mutex.lock().unwrap().subscribe(group);
+            }
+        }
     }
-}
\ No newline at end of file
+
+    // Resolves lifetimes that refer to this connection or its user
+    pub fn bind_lifetime(&self, lifetime: Lifetime, permission: &Permission) -> Lifetime {
+        lifetime.bind(&self.id, &permission.name)
+    }
+}
+
+// The connection lifetime and its references end with the connection, no matter why the connection ended
+// Linked groups lose the connection as subscriber and taken references are given back
+impl Drop for ConnectionState {
+    fn drop(&mut self) {
+        let mut kernel = match self.write_mutex.lock() {
+            Ok(kernel) => kernel,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let lifetime = Lifetime::Connection(Some(self.id.clone()));
+        let references = kernel.references.len();
+        let mut expired = kernel.expire_lifetime(&lifetime);
+        // Most connections never bind anything, they would only fill the log
+        if expired > 0 || kernel.references.len() < references {
+            let _ = kernel.record(&Origin::server(), Record::Expire(lifetime));
+        }
+        kernel.release(&self.id);
+        for group in self.linked.iter() {
+            expired += kernel.unsubscribe(group);
+        }
+
+        if expired > 0 {
+            kernel.refresh();
+        }
+    }
+}
@@ -1,20 +1,26 @@
-use crate::{lexer::{validate::Part, parse::parse_instruction}, dbop::{op_type::{Instruction, TransactionOp, Op}, execute::execute_transaction}};
+use crate::{lexer::{validate::Part, parse::parse_instruction}, dbop::{op_type::{AdminOp, Instruction, LifetimeOp, SessionOp, TransactionOp, Op}, response::Response, execute::{execute_create, resolve_lifetime, execute_drop, execute_dump, execute_expire, execute_load, execute_take, execute_transaction}}, error::Error, persistence::{rewrite::{rewrite_status, start_rewrite}, snapshot::take_snapshot}};
 
 use super::{connection_state::{ConnectionState, OperationMode}, permission::Permission};
 
 pub fn handle_instruction(
     parts: Vec<Part>,
     connection_state: &mut ConnectionState,
-    permission: &Permission,
-) -> Result<String, String> {
-    let instruction: Instruction;
+    permission: &mut Permission,
+) -> Result<Response, Error> {
+    let mut instruction: Instruction;
 
     match parse_instruction(parts) {
         Ok(parsed_instruction) => instruction = parsed_instruction,
-        Err(err) => return Err(err.to_string()),
+        Err(err) => return Err(Error::InvalidCommand(err.to_string())),
     }
 
-    permission.allow_instruction(&instruction)?;
+    // Lifetimes are bound first, since ownership depends on the connection and user
+    if let Instruction::Lifetime(lifetime_op) = instruction {
+        instruction = Instruction::Lifetime(lifetime_op.bind(&connection_state.id, &permission.name));
+    }
+
+    permission.allow_instruction(&instruction, &connection_state.id)?;
+    let origin = permission.origin(&connection_state.id);
 
     match instruction {
         Instruction::Transaction(transaction) => {
@@ -26,13 +32,13 @@ pub fn handle_instruction(
             match transaction {
                 TransactionOp::Sequence => {
                     if mode_is_sequence {
-                        return Err("Connection is already in sequence mode.".to_string());
+                        return Err(Error::TransactionState("Connection is already in sequence mode.".to_string()));
                     }
                     connection_state.mode = OperationMode::Transaction;
                 }
                 TransactionOp::Abort => {
                     if !mode_is_sequence {
-                        return Err("Connection is not in sequence mode.".to_string());
+                        return Err(Error::TransactionState("Connection is not in sequence mode.".to_string()));
                     }
 
                     connection_state.op_queue.clear();
@@ -40,22 +46,100 @@ pub fn handle_instruction(
                 }
                 TransactionOp::Execute => {
                     if !mode_is_sequence {
-                        return Err("Connection is not in sequence mode.".to_string());
+                        return Err(Error::TransactionState("Connection is not in sequence mode.".to_string()));
                     }
 
                     // Drain the old vector into the new vector to pass it along
                     let operations: Vec<Op> =
                         connection_state.op_queue.drain(..).into_iter().collect();
                     connection_state.mode = OperationMode::Default;
+
+                    // The permission may have changed since the operations were queued, nothing is applied if one is denied
+                    for operation in operations.iter() {
+                        permission.allow_operation(operation, &connection_state.id)?;
+                    }
+
                     return execute_transaction(
                         &connection_state.write_mutex,
                         &connection_state.read_handle,
+                        &connection_state.expiry_handle,
                         operations,
+                        &origin,
                     );
                 }
             }
         }
+        Instruction::Lifetime(lifetime_op) => {
+            match lifetime_op {
+                LifetimeOp::With(mut lifetime) => {
+                    if let Some(lt) = &lifetime {
+                        let resolved = resolve_lifetime(&connection_state.write_mutex, lt, &origin)?;
+                        connection_state.attach(&resolved);
+                        lifetime = Some(resolved);
+                    }
+                    connection_state.default_lifetime = lifetime;
+                }
+                LifetimeOp::Drop(lifetime) => {
+                    return execute_drop(&connection_state.write_mutex, &lifetime, &origin, |key| permission.can_access(key));
+                }
+                LifetimeOp::Create(lifetime) => {
+                    connection_state.attach(&lifetime);
+                    return execute_create(&connection_state.write_mutex, &lifetime, &origin).map_err(Error::Execution);
+                }
+                LifetimeOp::Expire(lifetime) => {
+                    return execute_expire(&connection_state.write_mutex, &lifetime, &origin, |key| permission.can_access(key));
+                }
+                LifetimeOp::Take(lifetime) => {
+                    return execute_take(&connection_state.write_mutex, &lifetime, &origin, |key| permission.can_access(key));
+                }
+            }
+        }
+        Instruction::Admin(admin_op) => {
+            match admin_op {
+                AdminOp::Snapshot => {
+                    return take_snapshot(&connection_state.write_mutex).map_err(Error::Execution);
+                }
+                AdminOp::Rewrite => {
+                    return start_rewrite(&connection_state.write_mutex, &connection_state.read_handle).map_err(Error::Execution);
+                }
+                AdminOp::RewriteStatus => {
+                    return rewrite_status(&connection_state.write_mutex).map_err(Error::Execution);
+                }
+                AdminOp::Dump => {
+                    return execute_dump(&connection_state.write_mutex).map_err(Error::Execution);
+                }
+                AdminOp::ReloadPermissions => {
+                    let names = connection_state.permissions.reload().map_err(Error::ReloadFailed)?;
+                    return Ok(Response::Text(format!("Reloaded {} permissions", names.len())));
+                }
+                AdminOp::Load(keys, values) => {
+                    return execute_load(&connection_state.write_mutex, keys, values, &origin);
+                }
+            }
+        }
+        Instruction::Session(session_op) => {
+            match session_op {
+                SessionOp::Format(format) => {
+                    connection_state.format = format;
+                }
+                SessionOp::Auth(token) => {
+                    let generation = connection_state.permissions.generation();
+                    let Some(authenticated) = connection_state.permissions.read().permission_from_auth_str(
+                        token.clone(),
+                        connection_state.address.clone(),
+                        permission.mode(),
+                    ) else {
+                        return Err(Error::PermissionDenied("Invalid token".to_string()));
+                    };
+
+                    *permission = authenticated;
+                    connection_state.authenticated = true;
+                    connection_state.token = Some(token);
+                    connection_state.generation = generation;
+                }
+            }
+        }
     }
 
-    Ok("Ok".to_string())
+    Ok(Response::Ok)
 }
\ No newline at end of file
@@ -1,39 +1,58 @@
-use crate::{lexer::{validate::Part, parse::parse_operation}, dbop::{op_type::Op, execute::execute_single}};
+use crate::{lexer::{validate::Part, parse::parse_operation}, dbop::{op_type::{Op, WriteOp}, execute::execute_single, response::Response}, error::Error};
 use super::{connection_state::{ConnectionState, OperationMode}, permission::Permission};
 
 pub fn handle_operation(
     parts: Vec<Part>,
     connection_state: &mut ConnectionState,
     permission: &Permission,
-) -> Result<String, String> {
+) -> Result<Response, Error> {
     // Todo implement return and write for Success and Failure of Operation
 
-    let operation: Op;
+    let mut operation: Op;
 
     match parse_operation(parts) {
         Ok(parsed_operation) => {
             operation = parsed_operation;
         }
         Err(err) => {
-            return Err(err.to_string());
+            return Err(Error::InvalidCommand(err.to_string()));
         }
     }
 
+    // Lifetimes have to be bound when queued, not when the transaction is executed
+    match &mut operation {
+        Op::Write(WriteOp::Put(lifetime, _, _)) => match lifetime {
+            Some(explicit) => *lifetime = Some(connection_state.bind_lifetime(explicit.clone(), permission)),
+            None => *lifetime = connection_state.default_lifetime.clone(),
+        },
+        Op::Write(WriteOp::Move(_, lifetime)) => {
+            *lifetime = connection_state.bind_lifetime(lifetime.clone(), permission);
+        }
+        _ => {}
+    }
+
     // Check if the command is allowed
-    permission.allow_operation(&operation)?;
+    permission.allow_operation(&operation, &connection_state.id)?;
+
+    // Writing into a linked group subscribes to it
+    if let Op::Write(WriteOp::Put(Some(lifetime), _, _) | WriteOp::Move(_, lifetime)) = &operation {
+        connection_state.attach(lifetime);
+    }
 
     match connection_state.mode {
         OperationMode::Default => {
             return execute_single(
                 &connection_state.write_mutex,
                 &connection_state.read_handle,
+                &connection_state.expiry_handle,
                 operation,
+                &permission.origin(&connection_state.id),
             )
         }
         OperationMode::Transaction => {
             // Queue new Operation here
             connection_state.op_queue.push(operation);
-            return Ok("+Queue".to_string());
+            return Ok(Response::Queued);
         }
     }
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
+pub mod cidr;
 pub mod connection;
 pub mod read_all_from_stream;
 mod connection_state;
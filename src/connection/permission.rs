@@ -1,14 +1,39 @@
 use crate::{
-    dbop::op_type::{Op, WriteOp, Instruction},
+    dbop::{op_type::{AdminOp, Op, WriteOp, Instruction, LifetimeOp}, origin::Origin},
+    error::Error,
+    lexer::validate::Lifetime,
     Mode,
 };
 
+use regex::Regex;
+
 use super::permission_list::PermissionState;
 
+// Turns a key pattern into a regex, * matches any characters and ? a single one
+fn glob(pattern: &str) -> Regex {
+    let mut regex = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).unwrap()
+}
+
 #[derive(Debug)]
 pub struct Permission {
     pub name: String,
     mode: Mode,
+    read: bool,
+    write: bool,
+    read_write: bool,
+    transaction: bool,
+    restricted: bool,
+    allow_keys: Vec<Regex>,
+    deny_keys: Vec<Regex>,
 }
 
 impl Permission {
@@ -16,33 +41,107 @@ impl Permission {
         return Permission {
             name: permission_state.name.clone(),
             mode: mode,
+            read: permission_state.read,
+            write: permission_state.write,
+            read_write: permission_state.read_write,
+            transaction: permission_state.transaction,
+            restricted: permission_state.restricted,
+            allow_keys: permission_state.allow_keys.iter().map(|pattern| glob(pattern)).collect(),
+            deny_keys: permission_state.deny_keys.iter().map(|pattern| glob(pattern)).collect(),
         };
     }
 
-    fn can_read(&self) -> Result<(), String> {
+    pub fn mode(&self) -> &Mode {
+        &self.mode
+    }
+
+    // Origin for operations issued on the given connection with this permission
+    pub fn origin(&self, connection: &str) -> Origin {
+        Origin::new(&self.name, connection, self.can_use_restricted().is_ok())
+    }
+
+    fn can_read(&self) -> Result<(), Error> {
+        if !self.read {
+            return Err(Error::PermissionDenied(format!("Permission {} can not read", self.name)));
+        }
         Ok(())
     }
 
-    fn can_write(&self) -> Result<(), String> {
+    fn can_write(&self) -> Result<(), Error> {
+        if !self.write {
+            return Err(Error::PermissionDenied(format!("Permission {} can not write", self.name)));
+        }
+        Ok(())
+    }
+
+    fn can_read_write(&self) -> Result<(), Error> {
+        if !self.read_write {
+            return Err(Error::PermissionDenied(format!("Permission {} can not use read write Commands", self.name)));
+        }
+        Ok(())
+    }
+
+    fn can_transaction(&self) -> Result<(), Error> {
+        if !self.transaction {
+            return Err(Error::PermissionDenied(format!("Permission {} can not use transactions", self.name)));
+        }
+        Ok(())
+    }
+
+    pub fn can_access(&self, key: &str) -> Result<(), Error> {
+        let denied = self.deny_keys.iter().any(|pattern| pattern.is_match(key));
+        let allowed = self.allow_keys.is_empty() || self.allow_keys.iter().any(|pattern| pattern.is_match(key));
+
+        if denied || !allowed {
+            return Err(Error::PermissionDenied(format!("Permission {} can not access key \"{}\"", self.name, key)));
+        }
         Ok(())
     }
 
-    fn can_transaction(&self) -> Result<(), String> {
+    // PURGE touches every key, which a permission limited to some keys must not do
+    fn can_access_all(&self) -> Result<(), Error> {
+        if !self.allow_keys.is_empty() || !self.deny_keys.is_empty() {
+            return Err(Error::PermissionDenied(format!("Permission {} is limited to some keys", self.name)));
+        }
         Ok(())
     }
 
-    fn can_use_restricted(&self) -> Result<(), String> {
+    // Every permission is restricted in test mode
+    fn can_use_restricted(&self) -> Result<(), Error> {
         match self.mode {
             Mode::Test => {}
+            _ if self.restricted => {}
             _ => {
-                return Err(format!("Can not use restricted Commands in mode {:?}", self.mode).to_string());
+                return Err(Error::PermissionDenied(format!(
+                    "Permission {} can not use restricted Commands in mode {:?}",
+                    self.name, self.mode
+                )));
             }
         }
 
         Ok(())
     }
 
-    pub fn allow_operation(&self, op: &Op) -> Result<(), String> {
+    // Lifetimes owned by other users or connections can only be used with restricted rights
+    fn can_bind(&self, lifetime: &Lifetime, connection: &str) -> Result<(), Error> {
+        match lifetime.root() {
+            Lifetime::User(Some(user)) if user != &self.name => self.can_use_restricted(),
+            Lifetime::Connection(Some(id)) if id != connection => self.can_use_restricted(),
+            _ => Ok(()),
+        }
+    }
+
+    // Owned lifetimes can be expired [READ WRITE DROP EXPIRE], borrowed ones can not [READ WRITE DROP]
+    // References are owned by their creator, which is only known to the kernel and checked there
+    fn can_expire(&self, lifetime: &Lifetime) -> Result<(), Error> {
+        match lifetime {
+            Lifetime::Reference(_, _) => Ok(()),
+            lifetime if lifetime.is_owned() => Ok(()),
+            lifetime => Err(Error::PermissionDenied(format!("Lifetime {} is borrowed and can not be expired", lifetime))),
+        }
+    }
+
+    pub fn allow_operation(&self, op: &Op, connection: &str) -> Result<(), Error> {
         match op {
             Op::Read(_read) => {
                 self.can_read()?;
@@ -53,24 +152,68 @@ impl Permission {
                 match write {
                     WriteOp::Purge => {
                         self.can_use_restricted()?;
+                        self.can_access_all()?;
+                    }
+                    WriteOp::Move(_, lifetime) | WriteOp::Put(Some(lifetime), _, _) => {
+                        self.can_bind(lifetime, connection)?;
                     }
                     _ => {}
                 }
             }
+            // Read write operations change keys, so they need both on top of their own capability
             Op::ReadWrite(_read_write) => {
                 self.can_read()?;
                 self.can_write()?;
+                self.can_read_write()?;
             }
         }
 
+        for key in op.keys() {
+            self.can_access(key)?;
+        }
+
         Ok(())
     }
 
-    pub fn allow_instruction(&self, instruction: &Instruction) -> Result<(), String> {
+    pub fn allow_instruction(&self, instruction: &Instruction, connection: &str) -> Result<(), Error> {
         match instruction {
             Instruction::Transaction(_transaction) => {
                 self.can_transaction()?;
             }
+            Instruction::Lifetime(lifetime_op) => {
+                self.can_write()?;
+
+                match lifetime_op {
+                    LifetimeOp::Expire(lifetime) => {
+                        self.can_bind(lifetime, connection)?;
+                        self.can_expire(lifetime)?;
+                    }
+                    LifetimeOp::With(Some(lifetime))
+                    | LifetimeOp::Drop(lifetime)
+                    | LifetimeOp::Create(lifetime)
+                    | LifetimeOp::Take(lifetime) => {
+                        self.can_bind(lifetime, connection)?;
+                    }
+                    LifetimeOp::With(None) => {}
+                }
+            }
+            Instruction::Admin(admin_op) => {
+                self.can_use_restricted()?;
+
+                match admin_op {
+                    AdminOp::Load(keys, _) => {
+                        for key in keys {
+                            self.can_access(key)?;
+                        }
+                    }
+                    AdminOp::Dump => {
+                        self.can_access_all()?;
+                    }
+                    _ => {}
+                }
+            }
+            // Every connection may change its own settings
+            Instruction::Session(_session_op) => {}
         }
 
         Ok(())
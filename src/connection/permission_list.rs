@@ -1,8 +1,17 @@
 use serde::{Serialize, Deserialize};
-use std::fs;
+use std::{
+    fs,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock, RwLockReadGuard,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
 
 use crate::Mode;
-use super::permission::Permission;
+use super::{cidr::{is_allowed, Cidr}, permission::Permission};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PermissionList {
@@ -15,40 +24,177 @@ impl PermissionList {
         PermissionList { permissions: vec![], default: PermissionState::default() }
     }
 
-    pub fn from_path(optional_path: Option<String>) -> PermissionList {
-        let path: String;
-        match optional_path {
-            Some(p_path) => {path = p_path}
-            None => {return PermissionList::default();}
-        };
+    // Reads and validates the file, a broken file is an error instead of a panic
+    pub fn try_from_path(path: &str) -> Result<PermissionList, String> {
+        let perm_string = fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path, err))?;
 
-        let Ok(perm_string) = fs::read_to_string(path) else {return PermissionList::default()};
+        let list: PermissionList =
+            serde_json::from_str(&perm_string).map_err(|err| format!("Invalid permissions in {}: {}", path, err))?;
 
-        let json: Result<PermissionList, _> = serde_json::from_str(&perm_string);
+        list.validate()?;
+        Ok(list)
+    }
 
-        return json.unwrap();
+    fn validate(&self) -> Result<(), String> {
+        for permission in self.permissions.iter() {
+            if permission.name.is_empty() {
+                return Err("Every permission needs a name".to_string());
+            }
+            // An empty token would let AUTH "" into the permission
+            if permission.token.is_empty() {
+                return Err(format!("Permission {} needs a token", permission.name));
+            }
+        }
+        Ok(())
     }
 
-    pub fn permission_from_auth_str(&self, token: String, ip: String, mode: &Mode) -> Permission {
+    // Permission of connections that did not authenticate
+    pub fn default_permission(&self, mode: &Mode) -> Permission {
+        Permission::new(&self.default, mode.clone())
+    }
+
+    // None if no permission matches the token
+    pub fn permission_from_auth_str(&self, token: String, ip: String, mode: &Mode) -> Option<Permission> {
         for permission in self.permissions.iter() {
             if let Some(permission) = permission.match_into(&token, &ip, mode.clone()) {
-                return permission
+                return Some(permission)
             }
         }
 
-        return Permission::new(&self.default, mode.clone());
+        return None;
+    }
+
+    // Names of the permissions, the tokens are not printed
+    pub fn names(&self) -> Vec<&str> {
+        self.permissions.iter().map(|permission| &permission.name[..]).collect()
+    }
+}
+
+// Interval in which the permission file is checked for changes
+pub const PERMISSION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// The permissions of the server, swapped as a whole when the file is reloaded
+pub struct SharedPermissions {
+    list: RwLock<PermissionList>,
+    path: Option<String>,
+    // Counts the reloads, so connections notice that their permission has to be evaluated again
+    generation: AtomicUsize,
+    // Modification time of the file when it was loaded last
+    modified: Mutex<Option<SystemTime>>,
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl SharedPermissions {
+    pub fn new(list: PermissionList, path: Option<String>) -> Self {
+        let loaded = path.as_deref().and_then(modified);
+        SharedPermissions { list: RwLock::new(list), path, generation: AtomicUsize::new(0), modified: Mutex::new(loaded) }
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, PermissionList> {
+        self.list.read().unwrap()
+    }
+
+    pub fn generation(&self) -> usize {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    // The old permissions are kept if the file is invalid, returns the names of the new ones
+    pub fn reload(&self) -> Result<Vec<String>, String> {
+        let Some(path) = &self.path else {
+            return Err("No permission file to reload, start with --perm-path".to_string());
+        };
+
+        // Remembered before loading, so a broken file is only reported once
+        let mut loaded = self.modified.lock().unwrap();
+        *loaded = modified(path);
+
+        let list = PermissionList::try_from_path(path)?;
+        let names = list.names().iter().map(|name| name.to_string()).collect();
+
+        *self.list.write().unwrap() = list;
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        Ok(names)
+    }
+
+    // None if the file did not change since it was loaded
+    pub fn reload_if_modified(&self) -> Option<Result<Vec<String>, String>> {
+        let path = self.path.as_ref()?;
+        if modified(path) == *self.modified.lock().unwrap() {
+            return None;
+        }
+        Some(self.reload())
     }
 }
 
+// Polls the modification time of the permission file and reloads it on change
+pub fn spawn_permission_watcher(permissions: Arc<SharedPermissions>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(PERMISSION_POLL_INTERVAL);
+
+        match permissions.reload_if_modified() {
+            Some(Ok(names)) => println!("Reloaded permissions: {:?}", names),
+            Some(Err(err)) => println!("Keeping the old permissions: {}", err),
+            None => {}
+        }
+    })
+}
+
+// Capabilities that are left out of the file are granted, except restricted
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PermissionState {
     pub name: String,
-    pub token: String
+    pub token: String,
+    // GET EXISTS HAS META
+    #[serde(default = "granted")]
+    pub read: bool,
+    // PUT REPLACE DELETE ... and the lifetime instructions
+    #[serde(default = "granted")]
+    pub write: bool,
+    // POP REDUCE
+    #[serde(default = "granted")]
+    pub read_write: bool,
+    // SEQUENCE ABORT EXECUTE
+    #[serde(default = "granted")]
+    pub transaction: bool,
+    // PURGE, the admin instructions and lifetimes of other users and connections
+    #[serde(default)]
+    pub restricted: bool,
+    // Key patterns, * matches any characters and ? a single one. Without allow_keys every key is allowed
+    #[serde(default)]
+    pub allow_keys: Vec<String>,
+    // Denied keys win over allowed ones
+    #[serde(default)]
+    pub deny_keys: Vec<String>,
+    // Networks the token is valid from, like 10.0.0.0/8. Without allow_ips every network is allowed
+    #[serde(default)]
+    pub allow_ips: Vec<Cidr>,
+    // Denied networks win over allowed ones
+    #[serde(default)]
+    pub deny_ips: Vec<Cidr>,
+}
+
+fn granted() -> bool {
+    true
 }
 
 impl PermissionState {
     pub fn default() -> Self {
-        PermissionState { name: "default".to_string(), token: "".to_string() }
+        PermissionState {
+            name: "default".to_string(),
+            token: "".to_string(),
+            read: true,
+            write: true,
+            read_write: true,
+            transaction: true,
+            restricted: false,
+            allow_keys: vec![],
+            deny_keys: vec![],
+            allow_ips: vec![],
+            deny_ips: vec![],
+        }
     }
 
     pub fn match_into(&self, token: &String, ip: &String, mode: Mode) -> Option<Permission> {
@@ -56,6 +202,15 @@ impl PermissionState {
             return None
         }
 
-        return Some(Permission::new(&self, mode));
+        // An address that can not be read is only allowed if the networks are not limited
+        let allowed = match ip.parse::<IpAddr>() {
+            Ok(ip) => is_allowed(&self.allow_ips, &self.deny_ips, &ip),
+            Err(_) => self.allow_ips.is_empty() && self.deny_ips.is_empty(),
+        };
+        if !allowed {
+            return None
+        }
+
+        return Some(Permission::new(self, mode));
     }
 }
\ No newline at end of file
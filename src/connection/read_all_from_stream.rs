@@ -1,11 +1,19 @@
 use std::{net::TcpStream, io::{Error, ErrorKind, Read}};
 
 use super::connection::{BUFFER_SIZE, READ_TIME_OUT, END_OF_MESSAGE, MAX_MESSAGE_LENGTH, MAX_SOCKET_BUFFER_LENGTH};
+
+// A connection that starts with this byte sends length prefixed frames instead of terminated messages
+pub const FRAMED_HANDSHAKE: u8 = 0x01;
+// Size of the big endian length in front of every frame
+pub const FRAME_HEADER_LENGTH: usize = 4;
+// Most bytes of a frame that are read at once, the buffer only grows with the bytes that actually arrived
+const FRAME_CHUNK_LENGTH: usize = 64 * 1024;
+
 #[derive(Debug)]
 pub enum TcpError {
     MessageTooLong(usize),
-    MessageExceedsMaxLength(),
-    MessagePolluted(String),
+    // Carries the limit that was exceeded
+    MessageExceedsMaxLength(usize),
     MessageUtf8Error(),
     TcpTimeout(),
     TcpShutdown(),
@@ -13,111 +21,167 @@ pub enum TcpError {
     TcpUnkownError(Error),
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum Framing {
+    // Every message ends with END_OF_MESSAGE
+    Terminated,
+    // Every message starts with its length as 4 byte big endian
+    LengthPrefixed,
+}
 
-pub fn read_all_from_stream(mut stream: &TcpStream) -> Result<String, TcpError> {
-    let mut buffer = vec![0; BUFFER_SIZE];
-    let mut message = String::new();
-    let mut bytes_read;
-    let mut initial_read: bool = true;
-
-    loop {
-        match stream.read(&mut buffer) {
-            Ok(n) => bytes_read = n,
-            Err(err) => {
-                match err.kind() {
-                    ErrorKind::ConnectionReset => {
-                        return Err(TcpError::TcpShutdownFromClient());
-                    }
-                    ErrorKind::TimedOut => {
-                        return Err(TcpError::TcpTimeout());
-                    }
-                    _ => {
-                        return Err(TcpError::TcpUnkownError(err));
-                    }
-                }
-                // distinguish errors
+impl Framing {
+    // Responses are framed the same way as the requests of the connection
+    pub fn frame(&self, message: &str) -> Vec<u8> {
+        match self {
+            Framing::Terminated => {
+                let mut frame = Vec::with_capacity(message.len() + 1);
+                frame.extend_from_slice(message.as_bytes());
+                frame.push(END_OF_MESSAGE as u8);
+                frame
+            }
+            Framing::LengthPrefixed => {
+                let mut frame = Vec::with_capacity(FRAME_HEADER_LENGTH + message.len());
+                frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+                frame.extend_from_slice(message.as_bytes());
+                frame
             }
         }
+    }
+}
+
+// Collects bytes from the stream until a whole message arrived, the framing is chosen by the first byte
+pub struct MessageReader {
+    framing: Option<Framing>,
+    buffer: Vec<u8>,
+    // Bytes of a terminated message that was too long and is being skipped
+    discarded: Option<usize>,
+    max_frame_length: usize,
+}
 
-        // add timeout after the initial read
-        if initial_read {
-            let _ = stream.set_read_timeout(Some(READ_TIME_OUT));
+impl MessageReader {
+    pub fn new(max_frame_length: usize) -> Self {
+        MessageReader {
+            framing: None,
+            buffer: Vec::new(),
+            discarded: None,
+            max_frame_length,
         }
-        initial_read = false;
+    }
+
+    // Connections that did not send anything yet are answered like terminated ones
+    pub fn framing(&self) -> Framing {
+        self.framing.clone().unwrap_or(Framing::Terminated)
+    }
+
+    pub fn read_message(&mut self, mut stream: &TcpStream) -> Result<String, TcpError> {
+        let mut chunk = vec![0; BUFFER_SIZE];
+        let mut initial_read: bool = true;
 
-        match String::from_utf8(buffer) {
-            Ok(string) => {
-                message += &string[..bytes_read];
+        loop {
+            if let Some(message) = self.next_message() {
+                return message;
             }
-            Err(_err) => {
-                return Err(TcpError::MessageUtf8Error());
+
+            let bytes_read = match stream.read(&mut chunk) {
+                Ok(0) => return Err(TcpError::TcpShutdown()),
+                Ok(n) => n,
+                Err(err) => match err.kind() {
+                    ErrorKind::ConnectionReset => return Err(TcpError::TcpShutdownFromClient()),
+                    ErrorKind::TimedOut | ErrorKind::WouldBlock => return Err(TcpError::TcpTimeout()),
+                    _ => return Err(TcpError::TcpUnkownError(err)),
+                },
+            };
+
+            // add timeout after the initial read
+            if initial_read {
+                let _ = stream.set_read_timeout(Some(READ_TIME_OUT));
             }
-        }
-        // message += &String::from_utf8(buffer[..bytes_read]).unwrap();
+            initial_read = false;
 
-        // Problem when exactly 1024 characters are sent
-        if message.contains(END_OF_MESSAGE) {
-            break;
-        }
+            self.buffer.extend_from_slice(&chunk[..bytes_read]);
 
-        if message.len() == 0 {
-            return Err(TcpError::TcpShutdown());
+            // Large frames are read in larger chunks
+            if self.framing == Some(Framing::LengthPrefixed) && chunk.len() < FRAME_CHUNK_LENGTH {
+                chunk = vec![0; FRAME_CHUNK_LENGTH];
+            }
         }
+    }
 
-        if message.len() > MAX_MESSAGE_LENGTH {
-            // set time out for if exactly 3072 bytes are being sent.
-
-            // read out remaining bytes
-            let bytes: usize;
-            let mut peek_buf: [u8; MAX_SOCKET_BUFFER_LENGTH] = [0; MAX_SOCKET_BUFFER_LENGTH];
-            match stream.peek(&mut peek_buf) {
-                Ok(u) => {
-                    bytes = u;
-
-                    // Return if it exceeds max buffer length
-                    if u > (MAX_SOCKET_BUFFER_LENGTH - (MAX_MESSAGE_LENGTH + BUFFER_SIZE)) {
-                        return Err(TcpError::MessageExceedsMaxLength());
-                    }
-
-                    // Clear out buffer
-                    let mut temp_buf: [u8; 1] = [0; 1];
-                    for _ in 1..u {
-                        let _ = stream.read_exact(&mut temp_buf);
-                    }
-                }
-                Err(err) => match err.kind() {
-                    ErrorKind::ConnectionReset => {
-                        return Err(TcpError::TcpShutdownFromClient());
-                    }
-                    ErrorKind::TimedOut => {
-                        return Err(TcpError::TcpTimeout());
-                    }
-                    _ => {
-                        return Err(TcpError::TcpUnkownError(err));
-                    }
-                },
+    // Takes the next whole message out of the buffer, None if more bytes are needed
+    fn next_message(&mut self) -> Option<Result<String, TcpError>> {
+        if self.framing.is_none() {
+            let first = *self.buffer.first()?;
+            if first == FRAMED_HANDSHAKE {
+                self.buffer.remove(0);
+                self.framing = Some(Framing::LengthPrefixed);
+            } else {
+                self.framing = Some(Framing::Terminated);
             }
-            return Err(TcpError::MessageTooLong(
-                bytes + MAX_MESSAGE_LENGTH + BUFFER_SIZE,
-            ));
         }
 
-        buffer = vec![0; 1024];
+        match self.framing {
+            Some(Framing::LengthPrefixed) => self.next_frame(),
+            _ => self.next_terminated(),
+        }
     }
 
-    if message.chars().filter(|x| *x == END_OF_MESSAGE).count() > 1 {
-        // TODO
-        // give back an error for polluted data.
-        if let Some(index) = message.find(END_OF_MESSAGE) {
-            return Err(TcpError::MessagePolluted(
-                message[index..].to_string().replace(END_OF_MESSAGE, ""),
-            ));
-        } else {
-            return Err(TcpError::MessagePolluted(String::from("")));
+    fn next_frame(&mut self) -> Option<Result<String, TcpError>> {
+        if self.buffer.len() < FRAME_HEADER_LENGTH {
+            return None;
         }
+
+        let mut header = [0; FRAME_HEADER_LENGTH];
+        header.copy_from_slice(&self.buffer[..FRAME_HEADER_LENGTH]);
+        let length = u32::from_be_bytes(header) as usize;
+
+        // The rest of the frame can not be skipped without reading it, so the connection is closed
+        if length > self.max_frame_length {
+            return Some(Err(TcpError::MessageExceedsMaxLength(self.max_frame_length)));
+        }
+
+        // Nothing is reserved for the announced length, a client could otherwise make the server allocate it without sending anything
+        if self.buffer.len() < FRAME_HEADER_LENGTH + length {
+            return None;
+        }
+
+        let frame: Vec<u8> = self.buffer.drain(..FRAME_HEADER_LENGTH + length).skip(FRAME_HEADER_LENGTH).collect();
+        Some(decode(frame))
     }
 
-    message = message.replace(END_OF_MESSAGE, "");
+    fn next_terminated(&mut self) -> Option<Result<String, TcpError>> {
+        let end_of_message = END_OF_MESSAGE as u8;
+        let Some(position) = self.buffer.iter().position(|byte| *byte == end_of_message) else {
+            if self.buffer.len() > MAX_MESSAGE_LENGTH {
+                // Skip the message up to its end, unless it is so long that it is seen as spam
+                let discarded = self.discarded.unwrap_or(0) + self.buffer.len();
+                if discarded > MAX_SOCKET_BUFFER_LENGTH {
+                    return Some(Err(TcpError::MessageExceedsMaxLength(MAX_SOCKET_BUFFER_LENGTH)));
+                }
+                self.discarded = Some(discarded);
+                self.buffer.clear();
+            }
+            return None;
+        };
+
+        // Bytes after the end of message belong to the next messages and stay in the buffer
+        let message: Vec<u8> = self.buffer.drain(..position + 1).take(position).collect();
+
+        if let Some(discarded) = self.discarded.take() {
+            return Some(Err(TcpError::MessageTooLong(discarded + message.len())));
+        }
 
-    Ok(message)
+        if message.len() > MAX_MESSAGE_LENGTH {
+            return Some(Err(TcpError::MessageTooLong(message.len())));
+        }
+
+        Some(decode(message))
+    }
+}
+
+// Decoded once the whole message arrived, so characters split between reads stay intact
+fn decode(message: Vec<u8>) -> Result<String, TcpError> {
+    match String::from_utf8(message) {
+        Ok(message) => Ok(message),
+        Err(_) => Err(TcpError::MessageUtf8Error()),
+    }
 }
@@ -1,66 +1,238 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::Local;
+
+use crate::{error::Error, lexer::validate::Lifetime, persistence::record::Record};
 
 use super::{
-    op_type::Op, read::execute_read, read_write::execute_read_write, write::execute_write,
+    kernel::Kernel,
+    op_type::{Op, ReadOp, WriteOp},
+    origin::Origin,
+    read::{execute_meta, execute_read},
+    read_write::execute_read_write,
+    response::Response,
+    write::execute_write,
 };
-use evmap::{ReadHandle, WriteHandle};
+use evmap::ReadHandle;
 
 pub fn execute_single(
-    write_mutex: &Arc<Mutex<WriteHandle<String, String>>>,
+    write_mutex: &Arc<Mutex<Kernel>>,
     read_handle: &ReadHandle<String, String>,
+    expiry_handle: &ReadHandle<String, i64>,
     operation: Op,
-) -> Result<String, String> {
+    origin: &Origin,
+) -> Result<Response, Error> {
     match operation {
         Op::Write(write_op) => {
-            let mut write_handle = write_mutex.lock().unwrap();
-            let ret = execute_write(&mut write_handle, write_op);
-            write_handle.refresh();
+            let mut kernel = write_mutex.lock().unwrap();
+            let record = Record::Write(write_op.clone());
+            let ret = execute_write(&mut kernel, write_op, origin);
+            if ret.is_ok() {
+                kernel.record(origin, record).map_err(Error::Execution)?;
+            }
+            kernel.refresh();
             return ret;
         }
 
+        Op::Read(ReadOp::Meta(keys)) => {
+            let kernel = write_mutex.lock().unwrap();
+            return execute_meta(&kernel, keys).map_err(Error::Execution);
+        }
+
         Op::Read(read_op) => {
-            return execute_read(&read_handle, read_op);
+            return execute_read(read_handle, expiry_handle, read_op).map_err(Error::Execution);
         }
 
         Op::ReadWrite(readwrite_op) => {
-            let mut write_handle = write_mutex.lock().unwrap();
-            let ret = execute_read_write(&mut write_handle, readwrite_op);
-            write_handle.refresh();
+            let mut kernel = write_mutex.lock().unwrap();
+            let record = Record::ReadWrite(readwrite_op.clone());
+            let ret = execute_read_write(&mut kernel, readwrite_op, origin);
+            if ret.is_ok() {
+                kernel.record(origin, record).map_err(Error::Execution)?;
+            }
+            kernel.refresh();
             return ret;
         }
     };
 }
 
 pub fn execute_transaction(
-    write_mutex: &Arc<Mutex<WriteHandle<String, String>>>,
+    write_mutex: &Arc<Mutex<Kernel>>,
     read_handle: &ReadHandle<String, String>,
+    expiry_handle: &ReadHandle<String, i64>,
     operations: Vec<Op>,
-) -> Result<String, String> {
-    let mut write_handle = write_mutex.lock().unwrap();
+    origin: &Origin,
+) -> Result<Response, Error> {
+    let mut kernel = write_mutex.lock().unwrap();
+    // Changes that were applied, they are logged even if a later operation fails
+    let mut applied: Vec<Op> = vec![];
+    let mut failure: Option<Error> = None;
 
     // enumerate and have failure index and amount of completed commands and shit.
     for (index, operation) in operations.into_iter().enumerate() {
+        let logged = match operation {
+            Op::Read(_) => None,
+            _ => Some(operation.clone()),
+        };
+
         let res;
         match operation {
             Op::Write(write_op) => {
-                res = execute_write(&mut write_handle, write_op);
+                res = execute_write(&mut kernel, write_op, origin);
+            }
+            Op::Read(ReadOp::Meta(keys)) => {
+                res = execute_meta(&kernel, keys).map_err(Error::Execution);
             }
             Op::Read(read_op) => {
-                res = execute_read(&read_handle, read_op);
+                res = execute_read(read_handle, expiry_handle, read_op).map_err(Error::Execution);
             }
             Op::ReadWrite(readwrite_op) => {
-                res = execute_read_write(&mut write_handle, readwrite_op);
+                res = execute_read_write(&mut kernel, readwrite_op, origin);
             }
         }
 
         match res {
-            Ok(_) => {},
+            Ok(_) => applied.extend(logged),
             Err(err) => {
-                return Err(format!("{}){}", index, err))
+                failure = Some(Error::TransactionFailed { index, error: Box::new(err) });
+                break;
             },
         }
     }
 
-    write_handle.refresh();
-    Ok("Ok".to_string())
-}
\ No newline at end of file
+    let logged = match applied.is_empty() {
+        true => Ok(()),
+        false => kernel.record(origin, Record::Transaction(applied)),
+    };
+    kernel.refresh();
+
+    if let Some(err) = failure {
+        return Err(err);
+    }
+    logged.map_err(Error::Execution)?;
+    Ok(Response::Ok)
+}
+
+// Lifetimes used with WITH have to exist at that point
+pub fn resolve_lifetime(
+    write_mutex: &Arc<Mutex<Kernel>>,
+    lifetime: &Lifetime,
+    origin: &Origin,
+) -> Result<Lifetime, Error> {
+    let kernel = write_mutex.lock().unwrap();
+    kernel.resolve_reference(lifetime, &origin.connection)
+}
+
+// The origin becomes the creator of the reference
+pub fn execute_create(
+    write_mutex: &Arc<Mutex<Kernel>>,
+    lifetime: &Lifetime,
+    origin: &Origin,
+) -> Result<Response, String> {
+    let mut kernel = write_mutex.lock().unwrap();
+    kernel.create_reference(lifetime, &origin.name)?;
+    kernel.record(origin, Record::Create(lifetime.clone()))?;
+    Ok(Response::Ok)
+}
+
+// Keys of the lifetime are checked with access under the lock, so no key can slip in before it is applied
+pub fn execute_take(
+    write_mutex: &Arc<Mutex<Kernel>>,
+    lifetime: &Lifetime,
+    origin: &Origin,
+    access: impl Fn(&str) -> Result<(), Error>,
+) -> Result<Response, Error> {
+    let mut kernel = write_mutex.lock().unwrap();
+    let lifetime = kernel.resolve_reference(lifetime, &origin.connection)?;
+    for key in kernel.lifetimes.keys_of(&lifetime) {
+        access(&key)?;
+    }
+    kernel.take_reference(&lifetime, origin)?;
+    Ok(Response::Ok)
+}
+
+pub fn execute_expire(
+    write_mutex: &Arc<Mutex<Kernel>>,
+    lifetime: &Lifetime,
+    origin: &Origin,
+    access: impl Fn(&str) -> Result<(), Error>,
+) -> Result<Response, Error> {
+    let mut kernel = write_mutex.lock().unwrap();
+    let lifetime = kernel.resolve_reference(lifetime, &origin.connection)?;
+    kernel.check_creator(&lifetime, origin)?;
+    for key in kernel.keys_expiring_with(&lifetime) {
+        access(&key)?;
+    }
+    if kernel.expire_lifetime(&lifetime) > 0 {
+        kernel.refresh();
+    }
+    kernel.record(origin, Record::Expire(lifetime)).map_err(Error::Execution)?;
+    Ok(Response::Ok)
+}
+
+pub fn execute_drop(
+    write_mutex: &Arc<Mutex<Kernel>>,
+    lifetime: &Lifetime,
+    origin: &Origin,
+    access: impl Fn(&str) -> Result<(), Error>,
+) -> Result<Response, Error> {
+    let mut kernel = write_mutex.lock().unwrap();
+    let lifetime = kernel.resolve_reference(lifetime, &origin.connection)?;
+    for key in kernel.lifetimes.keys_of(&lifetime) {
+        access(&key)?;
+    }
+    if kernel.drop_lifetime(&lifetime) > 0 {
+        kernel.refresh();
+    }
+    kernel.record(origin, Record::Drop(lifetime)).map_err(Error::Execution)?;
+    Ok(Response::Ok)
+}
+
+// Every key that is alive and its values
+pub fn execute_dump(write_mutex: &Arc<Mutex<Kernel>>) -> Result<Response, String> {
+    let kernel = write_mutex.lock().unwrap();
+    let now = Local::now();
+
+    let mut dump: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    if let Some(map) = kernel.write_handle.read() {
+        for (key, values) in map.iter() {
+            if !kernel.lifetimes.is_expired(key, &now) {
+                dump.insert(key.clone(), values.iter().cloned().collect());
+            }
+        }
+    }
+
+    Ok(Response::Map(dump))
+}
+
+// Replaces the values of every key at once
+// Every key is checked before any is replaced, so either all or none are loaded
+pub fn execute_load(
+    write_mutex: &Arc<Mutex<Kernel>>,
+    keys: Vec<String>,
+    values: Vec<Vec<String>>,
+    origin: &Origin,
+) -> Result<Response, Error> {
+    let mut kernel = write_mutex.lock().unwrap();
+    for key in keys.iter() {
+        kernel.lifetimes.check_owner(key, origin)?;
+    }
+
+    let operations: Vec<WriteOp> = keys
+        .into_iter()
+        .zip(values)
+        .map(|(key, values)| WriteOp::Replace(key, values))
+        .collect();
+
+    for operation in operations.iter() {
+        execute_write(&mut kernel, operation.clone(), origin)?;
+    }
+
+    let logged = kernel.record(origin, Record::Transaction(operations.into_iter().map(Op::Write).collect()));
+    kernel.refresh();
+    logged.map_err(Error::Execution)?;
+    Ok(Response::Ok)
+}
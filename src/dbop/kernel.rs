@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Local};
+use evmap::WriteHandle;
+
+use crate::{
+    error::Error,
+    lexer::validate::Lifetime,
+    persistence::{record::Record, rewrite::RewriteStatus, snapshot::SnapshotStore, write_log::WriteLog},
+};
+
+use super::{lifetime_table::LifetimeTable, meta_map::MetaMap, origin::Origin};
+
+// Everything that has to be changed together is guarded by the same mutex
+pub struct Kernel {
+    pub write_handle: WriteHandle<String, String>,
+    pub lifetimes: LifetimeTable,
+    pub meta: MetaMap,
+    // Named lifetime references and the lifetime they are bound to
+    pub references: HashMap<String, Lifetime>,
+    // References and the name of the permission that created them
+    pub creators: HashMap<String, String>,
+    // References that were taken and the connection that took them
+    pub taken: HashMap<String, String>,
+    // Amount of connections subscribed to each linked group
+    pub subscribers: HashMap<String, usize>,
+    // Every change is appended here once it was applied, if persistence is enabled
+    pub log: Option<WriteLog>,
+    // Where snapshots are written to, if snapshots are enabled
+    pub snapshots: Option<SnapshotStore>,
+    // Progress of the latest rewrite of the write log
+    pub rewrite: RewriteStatus,
+}
+
+impl Kernel {
+    pub fn new(write_handle: WriteHandle<String, String>, expiry_handle: WriteHandle<String, i64>) -> Self {
+        Kernel {
+            write_handle,
+            lifetimes: LifetimeTable::new(expiry_handle),
+            meta: MetaMap::new(),
+            references: HashMap::new(),
+            creators: HashMap::new(),
+            taken: HashMap::new(),
+            subscribers: HashMap::new(),
+            log: None,
+            snapshots: None,
+            rewrite: RewriteStatus::Idle,
+        }
+    }
+
+    // Appends the applied change to the write log
+    pub fn record(&mut self, origin: &Origin, record: Record) -> Result<(), String> {
+        let Some(log) = self.log.as_mut() else { return Ok(()) };
+
+        log.append(origin, record).map_err(|err| {
+            println!("Failed to append to write log: {:?}", err);
+            "Change was applied but could not be written to the log".to_string()
+        })
+    }
+
+    pub fn refresh(&mut self) {
+        // Expiries are published first, so new keys are never visible without their expiry
+        self.lifetimes.flush();
+        self.write_handle.refresh();
+    }
+
+    // Deletes every key that lives under the lifetime and returns the amount of deleted keys
+    pub fn drop_lifetime(&mut self, lifetime: &Lifetime) -> usize {
+        let keys = self.lifetimes.keys_of(lifetime);
+        for key in keys.iter() {
+            self.lifetimes.unbind(key);
+            self.meta.remove(key);
+            self.write_handle.empty(key.clone());
+        }
+        keys.len()
+    }
+
+    // Ends the lifetime, which also ends every reference that is bound to it
+    pub fn expire_lifetime(&mut self, lifetime: &Lifetime) -> usize {
+        let mut expired = self.drop_lifetime(lifetime);
+
+        if let Lifetime::Reference(name, _) = lifetime {
+            self.references.remove(name);
+            self.creators.remove(name);
+            self.taken.remove(name);
+            return expired;
+        }
+
+        let children: Vec<String> = self
+            .references
+            .iter()
+            .filter(|(_, parent)| *parent == lifetime)
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        for name in children {
+            self.creators.remove(&name);
+            self.taken.remove(&name);
+            if let Some(parent) = self.references.remove(&name) {
+                expired += self.drop_lifetime(&Lifetime::Reference(name, Box::new(parent)));
+            }
+        }
+
+        expired
+    }
+
+    // Every key that expiring the lifetime deletes, including the keys of the references created for it
+    pub fn keys_expiring_with(&self, lifetime: &Lifetime) -> Vec<String> {
+        let mut keys = self.lifetimes.keys_of(lifetime);
+        if let Lifetime::Reference(_, _) = lifetime {
+            return keys;
+        }
+
+        for (name, parent) in self.references.iter().filter(|(_, parent)| *parent == lifetime) {
+            keys.extend(self.lifetimes.keys_of(&Lifetime::Reference(name.clone(), Box::new(parent.clone()))));
+        }
+        keys
+    }
+
+    // Expires every lifetime that ran out and returns the amount of deleted keys
+    pub fn reap(&mut self, now: &DateTime<Local>) -> usize {
+        let mut reaped: usize = 0;
+        for lifetime in self.lifetimes.expired(now) {
+            reaped += self.expire_lifetime(&lifetime);
+            let _ = self.record(&Origin::server(), Record::Expire(lifetime));
+        }
+
+        // References can run out without having any keys
+        let references: Vec<Lifetime> = self
+            .references
+            .iter()
+            .filter(|(_, parent)| matches!(parent.expiry(), Some(date) if date <= now))
+            .map(|(name, parent)| Lifetime::Reference(name.clone(), Box::new(parent.clone())))
+            .collect();
+        for reference in references {
+            reaped += self.expire_lifetime(&reference);
+            let _ = self.record(&Origin::server(), Record::Expire(reference));
+        }
+
+        reaped
+    }
+
+    // Connections and their subscriptions do not survive a restart, so neither do their lifetimes
+    pub fn expire_sessions(&mut self) -> usize {
+        let mut sessions: Vec<Lifetime> = self
+            .lifetimes
+            .lifetimes()
+            .into_iter()
+            .chain(self.references.values().cloned())
+            .filter(|lifetime| matches!(lifetime, Lifetime::Connection(_) | Lifetime::Linked(_)))
+            .collect();
+        sessions.sort_by_key(|lifetime| lifetime.to_string());
+        sessions.dedup();
+
+        let mut expired: usize = 0;
+        for lifetime in sessions {
+            expired += self.expire_lifetime(&lifetime);
+            let _ = self.record(&Origin::server(), Record::Expire(lifetime));
+        }
+        expired
+    }
+
+    pub fn subscribe(&mut self, group: &str) {
+        *self.subscribers.entry(group.to_string()).or_insert(0) += 1;
+    }
+
+    // The linked lifetime expires once the last subscriber is gone
+    pub fn unsubscribe(&mut self, group: &str) -> usize {
+        let Some(count) = self.subscribers.get_mut(group) else { return 0 };
+
+        *count -= 1;
+        if *count > 0 {
+            return 0;
+        }
+
+        self.subscribers.remove(group);
+        let lifetime = Lifetime::Linked(group.to_string());
+        let expired = self.expire_lifetime(&lifetime);
+        let _ = self.record(&Origin::server(), Record::Expire(lifetime));
+        expired
+    }
+
+    pub fn create_reference(&mut self, lifetime: &Lifetime, creator: &str) -> Result<(), String> {
+        let Lifetime::Reference(name, parent) = lifetime else {
+            return Err("Only Lifetime References can be created".to_string());
+        };
+
+        if self.references.contains_key(name) {
+            return Err(format!("Lifetime Reference {} already exists", name));
+        }
+
+        self.references.insert(name.clone(), *parent.clone());
+        self.creators.insert(name.clone(), creator.to_string());
+        Ok(())
+    }
+
+    // Only the creator of a reference may take or expire it, unless the origin is restricted
+    pub fn check_creator(&self, lifetime: &Lifetime, origin: &Origin) -> Result<(), Error> {
+        let Lifetime::Reference(name, _) = lifetime else { return Ok(()) };
+
+        match self.creators.get(name) {
+            Some(creator) if creator != &origin.name && !origin.restricted => {
+                Err(Error::PermissionDenied(format!("Lifetime Reference {} was created by another User", name)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Only the taking connection may use the reference afterwards
+    pub fn take_reference(&mut self, lifetime: &Lifetime, origin: &Origin) -> Result<(), Error> {
+        let Lifetime::Reference(name, _) = self.resolve_reference(lifetime, &origin.connection)? else {
+            return Err(Error::Execution("Only Lifetime References can be taken".to_string()));
+        };
+        self.check_creator(lifetime, origin)?;
+
+        self.taken.insert(name, origin.connection.clone());
+        Ok(())
+    }
+
+    // Gives back every reference the connection has taken
+    pub fn release(&mut self, connection: &str) {
+        self.taken.retain(|_, taker| taker != connection);
+    }
+
+    // References have to be created before they can be used, returns the lifetime as it was created
+    pub fn resolve_reference(&self, lifetime: &Lifetime, connection: &str) -> Result<Lifetime, Error> {
+        let Lifetime::Reference(name, parent) = lifetime else { return Ok(lifetime.clone()) };
+
+        let Some(registered) = self.references.get(name) else {
+            return Err(Error::Execution(format!("Lifetime Reference {} was not created", name)));
+        };
+
+        if let Some(taker) = self.taken.get(name) {
+            if taker != connection {
+                return Err(Error::PermissionDenied(format!("Lifetime Reference {} was taken by another Connection", name)));
+            }
+        }
+
+        match (registered, parent.as_ref()) {
+            // Dates are fixed on creation, relative dates would never match again
+            (Lifetime::Date(_), Lifetime::Date(_)) => {}
+            (registered, parent) if registered == parent => {}
+            (registered, parent) => {
+                return Err(Error::Execution(format!(
+                    "Lifetime Reference {} was created for {:?} not for {:?}",
+                    name, registered, parent
+                )))
+            }
+        }
+
+        Ok(Lifetime::Reference(name.clone(), Box::new(registered.clone())))
+    }
+}
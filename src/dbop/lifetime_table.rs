@@ -0,0 +1,123 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Local};
+use evmap::WriteHandle;
+
+use crate::{error::Error, lexer::validate::Lifetime};
+
+use super::origin::Origin;
+
+// Keeps track of which key lives under which lifetime and vice versa
+pub struct LifetimeTable {
+    bindings: HashMap<String, Lifetime>,
+    groups: HashMap<Lifetime, HashSet<String>>,
+    // Expiry dates in milliseconds, readable without locking the kernel
+    expiries: WriteHandle<String, i64>,
+}
+
+impl LifetimeTable {
+    pub fn new(mut expiries: WriteHandle<String, i64>) -> Self {
+        // evmap only tracks pending changes after the first refresh, before that flush would never publish them
+        expiries.refresh();
+        LifetimeTable {
+            bindings: HashMap::new(),
+            groups: HashMap::new(),
+            expiries,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Lifetime> {
+        self.bindings.get(key)
+    }
+
+    // Binds the key to the lifetime, replacing any previous binding
+    pub fn bind(&mut self, key: &str, lifetime: Lifetime) {
+        self.unbind(key);
+        if let Some(date) = lifetime.expiry() {
+            self.expiries.update(key.to_string(), date.timestamp_millis());
+        }
+        self.groups
+            .entry(lifetime.clone())
+            .or_default()
+            .insert(key.to_string());
+        self.bindings.insert(key.to_string(), lifetime);
+    }
+
+    // Binds the key to the static lifetime if it is not bound yet
+    pub fn bind_default(&mut self, key: &str) {
+        if !self.bindings.contains_key(key) {
+            self.bind(key, Lifetime::Static);
+        }
+    }
+
+    pub fn unbind(&mut self, key: &str) {
+        let Some(lifetime) = self.bindings.remove(key) else { return };
+
+        if lifetime.expiry().is_some() {
+            self.expiries.empty(key.to_string());
+        }
+
+        if let Some(keys) = self.groups.get_mut(&lifetime) {
+            keys.remove(key);
+            if keys.is_empty() {
+                self.groups.remove(&lifetime);
+            }
+        }
+    }
+
+    // Returns every key that lives under the lifetime
+    pub fn keys_of(&self, lifetime: &Lifetime) -> Vec<String> {
+        match self.groups.get(lifetime) {
+            Some(keys) => keys.iter().cloned().collect(),
+            None => vec![],
+        }
+    }
+
+    // Keys of a connection can only be changed on it, keys of a user by the user or restricted origins
+    pub fn check_owner(&self, key: &str, origin: &Origin) -> Result<(), Error> {
+        if self.is_expired(key, &origin.time) {
+            return Ok(());
+        }
+
+        match self.bindings.get(key).map(|lifetime| lifetime.root()) {
+            Some(Lifetime::Connection(Some(connection))) if connection != &origin.connection => {
+                Err(Error::PermissionDenied(format!("Key {} is owned by another Connection", key)))
+            }
+            Some(Lifetime::User(Some(user))) if user != &origin.name && !origin.restricted => {
+                Err(Error::PermissionDenied(format!("Key {} is owned by another User", key)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_expired(&self, key: &str, now: &DateTime<Local>) -> bool {
+        match self.bindings.get(key).and_then(|lifetime| lifetime.expiry()) {
+            Some(date) => date <= now,
+            None => false,
+        }
+    }
+
+    // Returns every lifetime that has keys
+    pub fn lifetimes(&self) -> Vec<Lifetime> {
+        self.groups.keys().cloned().collect()
+    }
+
+    // Returns every lifetime with keys that has run out
+    pub fn expired(&self, now: &DateTime<Local>) -> Vec<Lifetime> {
+        self.groups
+            .keys()
+            .filter(|lifetime| matches!(lifetime.expiry(), Some(date) if date <= now))
+            .cloned()
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.bindings.clear();
+        self.groups.clear();
+        self.expiries.purge();
+    }
+
+    pub fn flush(&mut self) {
+        self.expiries.flush();
+    }
+}
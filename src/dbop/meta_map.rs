@@ -0,0 +1,86 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Meta {
+    pub created: DateTime<Local>,
+    pub modified: DateTime<Local>,
+    // Name of the permission that wrote the key last
+    pub writer: String,
+}
+
+// Meta data for every key, the lifetime of a key is kept in the LifetimeTable
+pub struct MetaMap {
+    entries: HashMap<String, Meta>,
+    // Every change of a key goes through the meta data, so changed keys can be tracked here
+    changed: Option<HashSet<String>>,
+}
+
+impl MetaMap {
+    pub fn new() -> Self {
+        MetaMap {
+            entries: HashMap::new(),
+            changed: None,
+        }
+    }
+
+    // Remembers every key that is changed from now on
+    pub fn track_changes(&mut self) {
+        self.changed = Some(HashSet::new());
+    }
+
+    // Stops tracking and returns the keys that were changed
+    pub fn take_changes(&mut self) -> HashSet<String> {
+        self.changed.take().unwrap_or_default()
+    }
+
+    fn mark(&mut self, key: &str) {
+        if let Some(changed) = self.changed.as_mut() {
+            changed.insert(key.to_string());
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Meta> {
+        self.entries.get(key)
+    }
+
+    // Marks the key as written, creating the entry if necessary
+    pub fn touch(&mut self, key: &str, writer: &str, now: &DateTime<Local>) {
+        self.mark(key);
+        match self.entries.get_mut(key) {
+            Some(meta) => {
+                meta.modified = *now;
+                meta.writer = writer.to_string();
+            }
+            None => {
+                self.entries.insert(
+                    key.to_string(),
+                    Meta {
+                        created: *now,
+                        modified: *now,
+                        writer: writer.to_string(),
+                    },
+                );
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: &str, meta: Meta) {
+        self.mark(key);
+        self.entries.insert(key.to_string(), meta);
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.mark(key);
+        self.entries.remove(key);
+    }
+
+    pub fn clear(&mut self) {
+        if let Some(changed) = self.changed.as_mut() {
+            changed.extend(self.entries.keys().cloned());
+        }
+        self.entries.clear();
+    }
+}
@@ -2,4 +2,10 @@ pub mod op_type;
 pub mod write;
 pub mod read;
 pub mod read_write;
-pub mod execute;
\ No newline at end of file
+pub mod execute;
+pub mod kernel;
+pub mod lifetime_table;
+pub mod meta_map;
+pub mod origin;
+pub mod reaper;
+pub mod response;
\ No newline at end of file
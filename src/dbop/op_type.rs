@@ -1,13 +1,19 @@
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+use crate::lexer::validate::Lifetime;
+
+use super::response::ResponseFormat;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Op {
     Read(ReadOp),
     Write(WriteOp),
     ReadWrite(ReadWriteOp),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum WriteOp {
-    Put(Vec<String>, Vec<Vec<String>>), // PUT [KEYS] [[VALUES]] -> puts the value list for every key
+    Put(Option<Lifetime>, Vec<String>, Vec<Vec<String>>), // PUT LIFETIME [KEYS] [[VALUES]] -> puts the value list for every key
 
     // Delete types
     Delete(Vec<String>), // DELETE [KEYS] -> delete all keys + values
@@ -19,13 +25,37 @@ pub enum WriteOp {
     // Morph
     Retract(Vec<String>, Vec<String>), // RETRACT [KEYS] [VALUES_TO_RETRACT] -> retracts values if they exists from keys
 
+    // Lifetime
+    Move(Vec<String>, Lifetime), // MOVE [KEYS] LIFETIME -> binds existing keys to another lifetime
+
     // Restricted
     Purge,
 }
 
+impl Op {
+    // Every key the operation reads or changes, PURGE changes all of them and has none
+    pub fn keys(&self) -> Vec<&String> {
+        match self {
+            Op::Read(ReadOp::Get(keys) | ReadOp::Exists(keys) | ReadOp::Has(keys, _) | ReadOp::Meta(keys)) => {
+                keys.iter().collect()
+            }
+            Op::Write(
+                WriteOp::Put(_, keys, _)
+                | WriteOp::Delete(keys)
+                | WriteOp::Clear(keys)
+                | WriteOp::Retract(keys, _)
+                | WriteOp::Move(keys, _),
+            ) => keys.iter().collect(),
+            Op::Write(WriteOp::Replace(key, _)) => vec![key],
+            Op::Write(WriteOp::Purge) => vec![],
+            Op::ReadWrite(ReadWriteOp::Pop(key)) => vec![key],
+        }
+    }
+}
+
 // CRUD [C = Put, R = Get, U = Replace, D = Delete]
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ReadWriteOp {
     // Delete / Delete
     Pop(String), // POP [KEY] -> [POP_VALUE]
@@ -34,16 +64,41 @@ pub enum ReadWriteOp {
     // Reduce(Vec<String>, Regex), // REDUCE [KEY] -> [REMOVED_VALUES]
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ReadOp {
     // Read
     Get(Vec<String>),         // GET [KEYS] -> [[VALUES]]
     Exists(Vec<String>),      // EXISTS [KEYS] -> [BOOL]
     Has(Vec<String>, String), // HAS [KEYS] VALUE -> [BOOL], Maybe make has take multiple values and give back [[BOOL]]
+    Meta(Vec<String>),        // META [KEYS] -> [["KEY:VALUE"]]
 }
 
 pub enum Instruction {
     Transaction(TransactionOp),
+    Lifetime(LifetimeOp),
+    Admin(AdminOp),
+    Session(SessionOp),
+}
+
+// Settings of the connection itself
+#[derive(Debug)]
+pub enum SessionOp {
+    Format(ResponseFormat),
+    // Swaps the permission of the connection for the one of the token
+    Auth(String),
+}
+
+// Server maintenance, only allowed for restricted permissions
+#[derive(Debug)]
+pub enum AdminOp {
+    Snapshot,
+    Rewrite,
+    RewriteStatus,
+    Dump,
+    // Replaces the values of the keys
+    Load(Vec<String>, Vec<Vec<String>>),
+    // Reads the permission file again
+    ReloadPermissions,
 }
 
 #[derive(Debug)]
@@ -52,3 +107,25 @@ pub enum TransactionOp {
     Abort,
     Execute,
 }
+
+#[derive(Debug)]
+pub enum LifetimeOp {
+    With(Option<Lifetime>), // WITH LIFETIME -> sets the default lifetime for PUT, resets it without lifetime
+    Drop(Lifetime),         // DROP LIFETIME -> deletes every key under the lifetime
+    Create(Lifetime),       // CREATE [&LIFETIME NAME] -> creates a named lifetime reference
+    Expire(Lifetime),       // EXPIRE LIFETIME -> deletes every key under the lifetime and every reference to it
+    Take(Lifetime),         // TAKE [&LIFETIME NAME] -> only the taking connection may use the reference
+}
+
+impl LifetimeOp {
+    // Binds the lifetime of the instruction to the connection and user
+    pub fn bind(self, connection_id: &str, user: &str) -> LifetimeOp {
+        match self {
+            LifetimeOp::With(lifetime) => LifetimeOp::With(lifetime.map(|lt| lt.bind(connection_id, user))),
+            LifetimeOp::Drop(lifetime) => LifetimeOp::Drop(lifetime.bind(connection_id, user)),
+            LifetimeOp::Create(lifetime) => LifetimeOp::Create(lifetime.bind(connection_id, user)),
+            LifetimeOp::Expire(lifetime) => LifetimeOp::Expire(lifetime.bind(connection_id, user)),
+            LifetimeOp::Take(lifetime) => LifetimeOp::Take(lifetime.bind(connection_id, user)),
+        }
+    }
+}
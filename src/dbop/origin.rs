@@ -0,0 +1,31 @@
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+// Identifies who issued an operation and when
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Origin {
+    // Name of the permission the operation was issued with
+    pub name: String,
+    // Id of the connection the operation was issued on
+    pub connection: String,
+    // Restricted origins may touch keys owned by other users
+    pub restricted: bool,
+    // Operations are executed as of this time, so replaying them gives the same result
+    pub time: DateTime<Local>,
+}
+
+impl Origin {
+    pub fn new(name: &str, connection: &str, restricted: bool) -> Self {
+        Origin {
+            name: name.to_string(),
+            connection: connection.to_string(),
+            restricted,
+            time: Local::now(),
+        }
+    }
+
+    // Origin for changes the server makes on its own, e.g. expiring lifetimes
+    pub fn server() -> Self {
+        Origin::new("server", "", true)
+    }
+}
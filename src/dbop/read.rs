@@ -1,43 +1,81 @@
+use chrono::{Local, SecondsFormat};
 use evmap::ReadHandle;
-use super::op_type::*;
+use super::{kernel::Kernel, op_type::*, response::Response};
+
+// Expired keys stay in the map until they are reaped, but must not be readable anymore
+fn is_expired(expiry_handle: &ReadHandle<String, i64>, key: &str, now: i64) -> bool {
+    match expiry_handle.get_one(key) {
+        Some(expiry) => *expiry <= now,
+        None => false,
+    }
+}
 
 pub fn execute_read(
     read_handle: &ReadHandle<String, String>,
+    expiry_handle: &ReadHandle<String, i64>,
     operation: ReadOp,
-) -> Result<String, String> {
+) -> Result<Response, String> {
+    let now = Local::now().timestamp_millis();
 
-    let ret: String;
+    let ret: Response;
     match operation {
         ReadOp::Get(keys) => {
             let mut get: Vec<Vec<String>> = Vec::new();
             for key in keys {
-                if let Some(results) = read_handle.get(&key[..]) {
+                if is_expired(expiry_handle, &key, now) {
+                    get.push(Vec::new());
+                } else if let Some(results) = read_handle.get(&key[..]) {
                     get.push((&*results).iter().map(|x| x.clone()).collect());
                 } else {
                     get.push(Vec::new());
                 }
             }
-            ret = format!("{:?}", get);
+            ret = Response::Values(get);
         }
 
         // Exists []
         ReadOp::Exists(keys) => {
             let exists: Vec<bool> = keys
                 .into_iter()
-                .map(|x| read_handle.contains_key(&x))
+                .map(|x| read_handle.contains_key(&x) && !is_expired(expiry_handle, &x, now))
                 .collect();
-            ret = format!("{:?}", exists);
+            ret = Response::Bools(exists);
         }
 
         // Has
         ReadOp::Has(keys, value) => {
             let has: Vec<bool> = keys
                 .into_iter()
-                .map(|key| read_handle.contains_value(&key, &value))
+                .map(|key| read_handle.contains_value(&key, &value) && !is_expired(expiry_handle, &key, now))
                 .collect();
-            ret = format!("{:?}", has);
+            ret = Response::Bools(has);
+        }
+
+        // Meta data is kept in the kernel, see execute_meta
+        ReadOp::Meta(_) => {
+            return Err("META can not be read without the kernel".to_string());
         }
     };
 
     return Ok(ret);
-}
\ No newline at end of file
+}
+// Meta data is structured as "KEY:VALUE" entries, see doc/internal/meta_map.md
+pub fn execute_meta(kernel: &Kernel, keys: Vec<String>) -> Result<Response, String> {
+    let now = Local::now();
+    let mut metas: Vec<Vec<String>> = Vec::new();
+
+    for key in keys {
+        let mut entries: Vec<String> = Vec::new();
+        if let (Some(meta), false) = (kernel.meta.get(&key), kernel.lifetimes.is_expired(&key, &now)) {
+            entries.push(format!("CREATED:{}", meta.created.to_rfc3339_opts(SecondsFormat::Millis, false)));
+            entries.push(format!("MODIFIED:{}", meta.modified.to_rfc3339_opts(SecondsFormat::Millis, false)));
+            if let Some(lifetime) = kernel.lifetimes.get(&key) {
+                entries.push(format!("LT:{}", lifetime));
+            }
+            entries.push(format!("WRITER:{}", meta.writer));
+        }
+        metas.push(entries);
+    }
+
+    Ok(Response::Values(metas))
+}
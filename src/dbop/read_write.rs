@@ -1,28 +1,36 @@
-use super::op_type::*;
-use evmap::WriteHandle;
+use crate::error::Error;
+
+use super::{kernel::Kernel, op_type::*, origin::Origin, response::Response};
 
 pub fn execute_read_write(
-    write_handle: &mut WriteHandle<String, String>,
+    kernel: &mut Kernel,
     operation: ReadWriteOp,
-) -> Result<String, String> {
-    let res: String;
+    origin: &Origin,
+) -> Result<Response, Error> {
+    let Kernel { write_handle, lifetimes, meta, .. } = kernel;
+    let res: Vec<String>;
     match operation {
         ReadWriteOp::Pop(key) => {
+            lifetimes.check_owner(&key, origin)?;
             let _pop: Option<Vec<String>>;
             match write_handle.get(&key) {
+                // Expired values are gone, even if they were not reaped yet
+                Some(_) if lifetimes.is_expired(&key, &origin.time) => {
+                    res = vec![];
+                }
                 Some(values) => {
-                    let popped: Vec<String> = values.iter().map(|x| x.clone()).collect();
-                    res = format!("{:?}", popped);
+                    res = values.iter().map(|x| x.clone()).collect();
                 }
                 None => {
-                    let empty: Vec<String> = vec![];
-                    res = format!("{:?}", empty);
+                    res = vec![];
                 }
             }
+            lifetimes.unbind(&key);
+            meta.remove(&key);
             write_handle.empty(key);
 
         }
     }
 
-    return Ok(res);
+    return Ok(Response::List(res));
 }
@@ -0,0 +1,24 @@
+use std::{
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use chrono::Local;
+
+use super::kernel::Kernel;
+
+// Time between two sweeps for expired keys
+pub const REAP_INTERVAL: Duration = Duration::new(1, 0);
+
+// Expired keys are hidden from reads right away, the reaper removes them from the map
+pub fn spawn_reaper(write_mutex: Arc<Mutex<Kernel>>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(REAP_INTERVAL);
+
+        let mut kernel = write_mutex.lock().unwrap();
+        if kernel.reap(&Local::now()) > 0 {
+            kernel.refresh();
+        }
+    })
+}
@@ -0,0 +1,125 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::error::Error;
+
+// Legacy and text errors start with it, so clients can tell them from results
+pub const ERR_PREFIX: &str = "Err: ";
+
+// Result of a successful command, encoded by the format of the connection
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    // The operation was queued for the transaction
+    Queued,
+    // Values per key, e.g. GET
+    Values(Vec<Vec<String>>),
+    // A flag per key, e.g. EXISTS
+    Bools(Vec<bool>),
+    List(Vec<String>),
+    Text(String),
+    Map(BTreeMap<String, Vec<String>>),
+}
+
+// How responses are written to the connection, chosen with FORMAT
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseFormat {
+    // Rust Debug output, kept for old clients
+    Legacy,
+    // Length prefixed text, see doc.md
+    Text,
+    Json,
+}
+
+impl ResponseFormat {
+    pub fn from_string(format: &str) -> Result<ResponseFormat, &'static str> {
+        match &format.to_lowercase()[..] {
+            "legacy" => Ok(ResponseFormat::Legacy),
+            "text" => Ok(ResponseFormat::Text),
+            "json" => Ok(ResponseFormat::Json),
+            _ => Err("Unknown Format, expected \"legacy\", \"text\" or \"json\""),
+        }
+    }
+
+    pub fn encode(&self, response: &Response) -> String {
+        match self {
+            ResponseFormat::Legacy => encode_legacy(response),
+            ResponseFormat::Text => encode_text(response),
+            ResponseFormat::Json => serde_json::to_string(response).unwrap(),
+        }
+    }
+
+    pub fn encode_error(&self, err: &Error) -> String {
+        let index = match err.index() {
+            Some(index) => index.to_string(),
+            None => "-".to_string(),
+        };
+
+        match self {
+            ResponseFormat::Legacy => match err.index() {
+                Some(index) => format!("{}{} {} {}: {}", ERR_PREFIX, err.code(), err.name(), index, err),
+                None => format!("{}{} {}: {}", ERR_PREFIX, err.code(), err.name(), err),
+            },
+            ResponseFormat::Text => format!("-{} {} {}\n{}", err.code(), err.name(), index, text_string(&err.to_string())),
+            ResponseFormat::Json => serde_json::json!({ "type": "error", "value": err.body() }).to_string(),
+        }
+    }
+}
+
+fn encode_legacy(response: &Response) -> String {
+    match response {
+        Response::Ok => "Ok".to_string(),
+        Response::Queued => "+Queue".to_string(),
+        Response::Values(values) => format!("{:?}", values),
+        Response::Bools(bools) => format!("{:?}", bools),
+        Response::List(list) => format!("{:?}", list),
+        Response::Text(text) => text.clone(),
+        Response::Map(map) => serde_json::to_string(map).unwrap(),
+    }
+}
+
+// Strings carry their length in bytes, so any content can be sent
+fn text_string(string: &str) -> String {
+    format!("${}\n{}\n", string.len(), string)
+}
+
+fn text_list(list: &[String]) -> String {
+    let mut text = format!("*{}\n", list.len());
+    for string in list {
+        text += &text_string(string);
+    }
+    text
+}
+
+fn encode_text(response: &Response) -> String {
+    match response {
+        Response::Ok => "+Ok\n".to_string(),
+        Response::Queued => "+Queue\n".to_string(),
+        Response::Values(values) => {
+            let mut text = format!("*{}\n", values.len());
+            for list in values {
+                text += &text_list(list);
+            }
+            text
+        }
+        Response::Bools(bools) => {
+            let mut text = format!("*{}\n", bools.len());
+            for flag in bools {
+                text += if *flag { "#t\n" } else { "#f\n" };
+            }
+            text
+        }
+        Response::List(list) => text_list(list),
+        Response::Text(string) => text_string(string),
+        Response::Map(map) => {
+            let mut text = format!("%{}\n", map.len());
+            for (key, values) in map {
+                text += &text_string(key);
+                text += &text_list(values);
+            }
+            text
+        }
+    }
+}
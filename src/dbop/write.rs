@@ -1,15 +1,50 @@
 use std::collections::HashSet;
 
-use evmap::WriteHandle;
-use super::op_type::*;
+use crate::{error::Error, lexer::validate::Lifetime};
 
-pub fn execute_write(write_handle: &mut WriteHandle<String, String>, operation: WriteOp) -> Result<String, String> {
-    // write_handle.map_into(f) read all for dump
+use super::{kernel::Kernel, op_type::*, origin::Origin, response::Response};
+
+pub fn execute_write(kernel: &mut Kernel, operation: WriteOp, origin: &Origin) -> Result<Response, Error> {
+    let mut operation = operation;
+    match &mut operation {
+        WriteOp::Put(Some(lifetime), _, _) | WriteOp::Move(_, lifetime) => {
+            *lifetime = kernel.resolve_reference(lifetime, &origin.connection)?;
+        }
+        _ => {}
+    }
+
+    let Kernel { write_handle, lifetimes, meta, .. } = kernel;
+    let now = origin.time;
+
+    // Every key is checked before any is changed, so either all or none are changed
+    match &operation {
+        WriteOp::Put(_, keys, _) | WriteOp::Delete(keys) | WriteOp::Clear(keys) | WriteOp::Retract(keys, _) => {
+            for key in keys.iter() {
+                lifetimes.check_owner(key, origin)?;
+            }
+        }
+        WriteOp::Replace(key, _) => lifetimes.check_owner(key, origin)?,
+        WriteOp::Move(_, _) | WriteOp::Purge => {}
+    }
 
     match operation {
         // Put operations
-        WriteOp::Put(keys, values_list) => {
+        WriteOp::Put(lifetime, keys, values_list) => {
             for (key, values) in keys.into_iter().zip(values_list.into_iter()) {
+                // Expired values that were not reaped yet must not be revived
+                if lifetimes.is_expired(&key, &now) {
+                    lifetimes.unbind(&key);
+                    meta.remove(&key);
+                    write_handle.empty(key.clone());
+                }
+                meta.touch(&key, &origin.name, &now);
+
+                // Without an explicit lifetime the key keeps its current one
+                match &lifetime {
+                    Some(lifetime) => lifetimes.bind(&key, lifetime.clone()),
+                    None => lifetimes.bind_default(&key),
+                }
+
                 // could reserve for optimization
                 for value in values {
                     write_handle.insert(key.clone(), value);
@@ -20,6 +55,8 @@ pub fn execute_write(write_handle: &mut WriteHandle<String, String>, operation:
         // Delete operations
         WriteOp::Delete(keys) => {
             for key in keys.into_iter() {
+                lifetimes.unbind(&key);
+                meta.remove(&key);
                 write_handle.empty(key);
             }
         }
@@ -29,6 +66,7 @@ pub fn execute_write(write_handle: &mut WriteHandle<String, String>, operation:
             for key in keys.into_iter() {
                 // only clear key if it exists else there would be problems with lifetimes
                 if write_handle.contains_key(&key) {
+                    meta.touch(&key, &origin.name, &now);
                     write_handle.clear(key);
                 }
             }
@@ -43,6 +81,8 @@ pub fn execute_write(write_handle: &mut WriteHandle<String, String>, operation:
                     continue
                 }
 
+                meta.touch(&key, &origin.name, &now);
+
                 // Need to copy here so it can be moved into the closure
                 let retracte_value_set_copy = retract_value_set.clone();
 
@@ -54,6 +94,12 @@ pub fn execute_write(write_handle: &mut WriteHandle<String, String>, operation:
         }
 
         WriteOp::Replace(key, values) => {
+            if lifetimes.is_expired(&key, &now) {
+                lifetimes.unbind(&key);
+                meta.remove(&key);
+            }
+            lifetimes.bind_default(&key);
+            meta.touch(&key, &origin.name, &now);
             if values.len() == 1 {
                 write_handle.update(key, values.into_iter().collect());
             } else {
@@ -69,10 +115,36 @@ pub fn execute_write(write_handle: &mut WriteHandle<String, String>, operation:
             }
         }
 
+        // Move operation
+        WriteOp::Move(keys, lifetime) => {
+            if let Lifetime::Connection(Some(connection)) = lifetime.root() {
+                if connection != &origin.connection {
+                    return Err(Error::PermissionDenied(
+                        "Keys can not be moved into the Lifetime of another Connection".to_string(),
+                    ));
+                }
+            }
+
+            // Check every key before moving any, so either all or none are moved
+            for key in keys.iter() {
+                if lifetimes.get(key).is_none() || lifetimes.is_expired(key, &now) {
+                    return Err(Error::Execution(format!("Key {} does not exist", key)));
+                }
+                lifetimes.check_owner(key, origin)?;
+            }
+
+            for key in keys.iter() {
+                lifetimes.bind(key, lifetime.clone());
+                meta.touch(key, &origin.name, &now);
+            }
+        }
+
         WriteOp::Purge => {
+            lifetimes.clear();
+            meta.clear();
             write_handle.purge();
         }
     }
 
-    return Ok(String::from("Ok"));
+    return Ok(Response::Ok);
 }
@@ -0,0 +1,120 @@
+use std::fmt;
+
+use serde::Serialize;
+
+// Every failure that is sent to a client, the codes and names are stable so clients can branch on them
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    // Lexing
+    IllegalCharacter { index: usize, character: char },
+    // Validation
+    InvalidToken { index: usize, message: String },
+    EmptyCommand,
+    // Parsing
+    InvalidCommand(String),
+    // Permission
+    PermissionDenied(String),
+    // The permission file could not be reloaded, the old permissions stay in place
+    ReloadFailed(String),
+    // Execution
+    Execution(String),
+    // Transaction state
+    TransactionState(String),
+    // The operation at index of the transaction failed, the operations before it were applied
+    TransactionFailed { index: usize, error: Box<Error> },
+    // Transport
+    MessageTooLong { length: usize, max: usize },
+    MessageExceedsMaxLength { max: usize },
+    InvalidUtf8,
+    Timeout(String),
+}
+
+// Wire representation of an error
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub code: u16,
+    pub name: &'static str,
+    // Index of the token that failed, counted from 1, or of the operation of a failed transaction
+    pub index: Option<usize>,
+    pub message: String,
+}
+
+impl Error {
+    pub fn code(&self) -> u16 {
+        match self {
+            Error::IllegalCharacter { .. } => 100,
+            Error::InvalidToken { .. } => 200,
+            Error::EmptyCommand => 201,
+            Error::InvalidCommand(_) => 300,
+            Error::PermissionDenied(_) => 400,
+            Error::ReloadFailed(_) => 401,
+            Error::Execution(_) => 500,
+            Error::TransactionState(_) => 600,
+            Error::TransactionFailed { .. } => 601,
+            Error::MessageTooLong { .. } => 700,
+            Error::MessageExceedsMaxLength { .. } => 701,
+            Error::InvalidUtf8 => 703,
+            Error::Timeout(_) => 704,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Error::IllegalCharacter { .. } => "ILLEGAL_CHARACTER",
+            Error::InvalidToken { .. } => "INVALID_TOKEN",
+            Error::EmptyCommand => "EMPTY_COMMAND",
+            Error::InvalidCommand(_) => "INVALID_COMMAND",
+            Error::PermissionDenied(_) => "PERMISSION_DENIED",
+            Error::ReloadFailed(_) => "RELOAD_FAILED",
+            Error::Execution(_) => "EXECUTION_FAILED",
+            Error::TransactionState(_) => "TRANSACTION_STATE",
+            Error::TransactionFailed { .. } => "TRANSACTION_FAILED",
+            Error::MessageTooLong { .. } => "MESSAGE_TOO_LONG",
+            Error::MessageExceedsMaxLength { .. } => "MESSAGE_EXCEEDS_MAX_LENGTH",
+            Error::InvalidUtf8 => "INVALID_UTF8",
+            Error::Timeout(_) => "TIMEOUT",
+        }
+    }
+
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            Error::IllegalCharacter { index, .. }
+            | Error::InvalidToken { index, .. }
+            | Error::TransactionFailed { index, .. } => Some(*index),
+            _ => None,
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        ErrorBody {
+            code: self.code(),
+            name: self.name(),
+            index: self.index(),
+            message: self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::IllegalCharacter { character, .. } => write!(f, "Illegal Character '{}'", character),
+            Error::InvalidToken { message, .. } => write!(f, "{}", message),
+            Error::EmptyCommand => write!(f, "Empty Command"),
+            Error::InvalidCommand(message)
+            | Error::PermissionDenied(message)
+            | Error::ReloadFailed(message)
+            | Error::Execution(message)
+            | Error::TransactionState(message)
+            | Error::Timeout(message) => write!(f, "{}", message),
+            Error::TransactionFailed { error, .. } => write!(f, "{} {}", error.name(), error),
+            Error::MessageTooLong { length, max } => {
+                write!(f, "Message was longer than allowed {} bytes, ({})", max, length)
+            }
+            Error::MessageExceedsMaxLength { max } => {
+                write!(f, "Message exceeded max length {}, Connection closed.", max)
+            }
+            Error::InvalidUtf8 => write!(f, "Message is not valid Utf8"),
+        }
+    }
+}
@@ -31,19 +31,22 @@ pub fn lex(string: String) -> Vec<Token> {
             '\n' => tok = Token::LINEBREAK,
             '"' => {
                 // VALUE
-                let expr = |l: &Lexer| -> bool {
-                    match l.view_next_char() {
-                        Some(c) => return c == '"',
-                        None => true,
+                // \" and \\ escape quotes and backslashes, any other backslash is kept as is
+                let mut value: Vec<char> = vec![];
+                loop {
+                    match l.next_char() {
+                        None | Some('"') => break,
+                        Some('\\') => match l.view_next_char() {
+                            Some(escaped @ ('"' | '\\')) => {
+                                l.next_char();
+                                value.push(escaped);
+                            }
+                            _ => value.push('\\'),
+                        },
+                        Some(c) => value.push(c),
                     }
-                };
-                next_token(&mut l, expr);
-                // Skip the ending quotes
-                l.next_char();
-                let mut buffer = l.collect_buffer();
-                // Remove the beginning quotes
-                buffer.retain(|&x| x != '"');
-                tok = Token::VALUE(buffer);
+                }
+                tok = Token::VALUE(value);
             }
             _ => {
                 // KEYWORD
@@ -88,12 +91,17 @@ pub fn lex(string: String) -> Vec<Token> {
         .collect();
 }
 
+// Escapes the value so it lexes back to the same value
+pub fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
 fn is_ident(ch: char) -> bool {
     ('a' <= ch && ch <= 'z') || ('A' <= ch && ch <= 'Z') || ('0' <= ch && ch <= '9') || ch == '_'
 }
 
 fn is_lifetime(ch: char) -> bool {
-    ch == '\'' || ch == 's' || ch == 'd' || ch == 'u' || ch == 'c'
+    ch == '\'' || ch == 's' || ch == 'd' || ch == 'u' || ch == 'c' || ch == 'l'
 }
 
 struct Lexer {
@@ -1,8 +1,11 @@
 use super::{
     token::{Keyword, KeywordType},
-    validate::Part,
+    validate::{Lifetime, Part},
+};
+use crate::dbop::{
+    op_type::{AdminOp, Instruction, LifetimeOp, SessionOp, Op, ReadOp, ReadWriteOp, WriteOp, TransactionOp},
+    response::ResponseFormat,
 };
-use crate::dbop::op_type::{Instruction, Op, ReadOp, ReadWriteOp, WriteOp, TransactionOp};
 
 pub fn parse_operation(parts: Vec<Part>) -> Result<Op, &'static str> {
     match parts.get(0).unwrap() {
@@ -18,12 +21,14 @@ pub fn parse_operation(parts: Vec<Part>) -> Result<Op, &'static str> {
                         Keyword::GET => return parse_get(parts),
                         Keyword::HAS => return parse_has(parts),
                         Keyword::EXISTS => return parse_exists(parts),
+                        Keyword::META => return parse_meta(parts),
                         // Write
                         Keyword::PUT => return parse_put(parts),
                         Keyword::DELETE => return parse_delete(parts),
                         Keyword::CLEAR => return parse_clear(parts),
                         Keyword::REPLACE => return parse_replace(parts),
                         Keyword::RETRACT => return parse_retract(parts),
+                        Keyword::MOVE => return parse_move(parts),
                         // Restricted-Write
                         Keyword::PURGE => return parse_purge(parts),
                         // Read-Write
@@ -50,6 +55,18 @@ pub fn parse_instruction(parts: Vec<Part>) -> Result<Instruction, &'static str>
                         Keyword::SEQEUENCE => {return Ok(Instruction::Transaction(TransactionOp::Sequence))},
                         Keyword::ABORT => {return Ok(Instruction::Transaction(TransactionOp::Abort))},
                         Keyword::EXECUTE => {return Ok(Instruction::Transaction(TransactionOp::Execute))},
+                        Keyword::WITH => {return parse_with(parts)},
+                        Keyword::DROP => {return parse_drop(parts)},
+                        Keyword::CREATE => {return parse_create(parts)},
+                        Keyword::EXPIRE => {return parse_expire(parts)},
+                        Keyword::TAKE => {return parse_take(parts)},
+                        Keyword::SNAPSHOT => {return Ok(Instruction::Admin(AdminOp::Snapshot))},
+                        Keyword::REWRITE => {return parse_rewrite(parts)},
+                        Keyword::DUMP => {return Ok(Instruction::Admin(AdminOp::Dump))},
+                        Keyword::LOAD => {return parse_load(parts)},
+                        Keyword::RELOAD => {return parse_reload(parts)},
+                        Keyword::FORMAT => {return parse_format(parts)},
+                        Keyword::AUTH => {return parse_auth(parts)},
                         _ => {}
                     }
                     return Err("Instruction does not exist")
@@ -101,6 +118,19 @@ fn match_into_value(val: Option<&Part>) -> Result<String, &'static str> {
     }
 }
 
+fn match_into_lifetime(val: Option<&Part>) -> Result<Lifetime, &'static str> {
+    match val {
+        Some(inner_val) => match inner_val {
+            Part::Lifetime { reference_name: None, lifetime } => return Ok(lifetime.clone()),
+            Part::Lifetime { reference_name: Some(name), lifetime } => {
+                return Ok(Lifetime::Reference(name.clone(), Box::new(lifetime.clone())))
+            }
+            _ => return Err("DEV Failed to match into lifetime."),
+        },
+        None => return Err("DEV match into lifetime part was not found."),
+    }
+}
+
 // READ
 // GET [KEYS]
 fn parse_get(parts: Vec<Part>) -> Result<Op, &'static str> {
@@ -140,23 +170,48 @@ fn parse_exists(parts: Vec<Part>) -> Result<Op, &'static str> {
     return Ok(Op::Read(ReadOp::Exists(keys)));
 }
 
+// META [KEYS]
+fn parse_meta(parts: Vec<Part>) -> Result<Op, &'static str> {
+    let keys: Vec<String>;
+    if parts.len() != 2 {
+        return Err("META requires 1 Argument: <Keys>");
+    }
+
+    load_or_err!(keys, match_into_values(parts.get(1)));
+
+    return Ok(Op::Read(ReadOp::Meta(keys)));
+}
+
 // WRITE
 fn parse_put(parts: Vec<Part>) -> Result<Op, &'static str> {
-    // GET: [KEYS] [[VALUES]]
+    // PUT: LIFETIME [KEYS] [[VALUES]]
+    let lifetime: Option<Lifetime>;
     let keys: Vec<String>;
     let values: Vec<Vec<String>>;
-    if parts.len() != 3 {
-        return Err("PUT requires 2 Arguments: <Keys> <<Values>>");
+    // Offset of the keys, the lifetime is optional
+    let offset: usize;
+    match parts.len() {
+        3 => {
+            lifetime = None;
+            offset = 1;
+        }
+        4 => {
+            let lt: Lifetime;
+            load_or_err!(lt, match_into_lifetime(parts.get(1)));
+            lifetime = Some(lt);
+            offset = 2;
+        }
+        _ => return Err("PUT requires 2 Arguments and an optional Lifetime: <Lifetime> <Keys> <<Values>>"),
     }
 
-    load_or_err!(keys, match_into_values(parts.get(1)));
-    load_or_err!(values, match_into_nested(parts.get(2)));
+    load_or_err!(keys, match_into_values(parts.get(offset)));
+    load_or_err!(values, match_into_nested(parts.get(offset + 1)));
 
     if keys.len() != values.len() {
         return Err("Amount of Keys must match amount of values provided.");
     }
 
-    return Ok(Op::Write(WriteOp::Put(keys, values)));
+    return Ok(Op::Write(WriteOp::Put(lifetime, keys, values)));
 }
 
 fn parse_delete(parts: Vec<Part>) -> Result<Op, &'static str> {
@@ -209,6 +264,21 @@ fn parse_retract(parts: Vec<Part>) -> Result<Op, &'static str> {
     return Ok(Op::Write(WriteOp::Retract(keys, values)));
 }
 
+// MOVE [KEYS] LIFETIME
+fn parse_move(parts: Vec<Part>) -> Result<Op, &'static str> {
+    let keys;
+    let lifetime;
+
+    if parts.len() != 3 {
+        return Err("MOVE requires 2 Arguments: <Keys> <Lifetime>");
+    }
+
+    load_or_err!(keys, match_into_values(parts.get(1)));
+    load_or_err!(lifetime, match_into_lifetime(parts.get(2)));
+
+    return Ok(Op::Write(WriteOp::Move(keys, lifetime)));
+}
+
 fn parse_purge(parts: Vec<Part>) -> Result<Op, &'static str> {
     if parts.len() != 1 {
         return Err("PURGE requires no Arguments");
@@ -228,3 +298,154 @@ fn parse_pop(parts: Vec<Part>) -> Result<Op, &'static str> {
 
     return Ok(Op::ReadWrite(ReadWriteOp::Pop(key)));
 }
+
+// INSTRUCTIONS
+// WITH LIFETIME
+fn parse_with(parts: Vec<Part>) -> Result<Instruction, &'static str> {
+    let lifetime: Lifetime;
+
+    match parts.len() {
+        1 => return Ok(Instruction::Lifetime(LifetimeOp::With(None))),
+        2 => {}
+        _ => return Err("WITH takes 1 optional Argument: <Lifetime>"),
+    }
+
+    load_or_err!(lifetime, match_into_lifetime(parts.get(1)));
+
+    return Ok(Instruction::Lifetime(LifetimeOp::With(Some(lifetime))));
+}
+
+// DROP LIFETIME
+fn parse_drop(parts: Vec<Part>) -> Result<Instruction, &'static str> {
+    let lifetime: Lifetime;
+
+    if parts.len() != 2 {
+        return Err("DROP requires 1 Argument: <Lifetime>");
+    }
+
+    load_or_err!(lifetime, match_into_lifetime(parts.get(1)));
+
+    if lifetime == Lifetime::Static {
+        return Err("Static Lifetime can not be dropped");
+    }
+
+    return Ok(Instruction::Lifetime(LifetimeOp::Drop(lifetime)));
+}
+
+// CREATE [&LIFETIME NAME]
+fn parse_create(parts: Vec<Part>) -> Result<Instruction, &'static str> {
+    let lifetime: Lifetime;
+
+    if parts.len() != 2 {
+        return Err("CREATE requires 1 Argument: <Lifetime Reference>");
+    }
+
+    load_or_err!(lifetime, match_into_lifetime(parts.get(1)));
+
+    if !matches!(lifetime, Lifetime::Reference(_, _)) {
+        return Err("CREATE only creates Lifetime References");
+    }
+
+    return Ok(Instruction::Lifetime(LifetimeOp::Create(lifetime)));
+}
+
+// EXPIRE LIFETIME
+fn parse_expire(parts: Vec<Part>) -> Result<Instruction, &'static str> {
+    let lifetime: Lifetime;
+
+    if parts.len() != 2 {
+        return Err("EXPIRE requires 1 Argument: <Lifetime>");
+    }
+
+    load_or_err!(lifetime, match_into_lifetime(parts.get(1)));
+
+    return Ok(Instruction::Lifetime(LifetimeOp::Expire(lifetime)));
+}
+
+// TAKE [&LIFETIME NAME]
+fn parse_take(parts: Vec<Part>) -> Result<Instruction, &'static str> {
+    let lifetime: Lifetime;
+
+    if parts.len() != 2 {
+        return Err("TAKE requires 1 Argument: <Lifetime Reference>");
+    }
+
+    load_or_err!(lifetime, match_into_lifetime(parts.get(1)));
+
+    if !matches!(lifetime, Lifetime::Reference(_, _)) {
+        return Err("TAKE only takes Lifetime References");
+    }
+
+    return Ok(Instruction::Lifetime(LifetimeOp::Take(lifetime)));
+}
+
+// REWRITE OPTIONAL[STATUS]
+fn parse_rewrite(parts: Vec<Part>) -> Result<Instruction, &'static str> {
+    match parts.len() {
+        1 => return Ok(Instruction::Admin(AdminOp::Rewrite)),
+        2 => {}
+        _ => return Err("REWRITE takes 1 optional Argument: STATUS"),
+    }
+
+    match parts.get(1) {
+        Some(Part::Keyword { keyword: Keyword::STATUS, .. }) => {
+            return Ok(Instruction::Admin(AdminOp::RewriteStatus))
+        }
+        _ => return Err("REWRITE takes 1 optional Argument: STATUS"),
+    }
+}
+
+// RELOAD PERMISSIONS
+fn parse_reload(parts: Vec<Part>) -> Result<Instruction, &'static str> {
+    match (parts.len(), parts.get(1)) {
+        (2, Some(Part::Keyword { keyword: Keyword::PERMISSIONS, .. })) => {
+            return Ok(Instruction::Admin(AdminOp::ReloadPermissions))
+        }
+        _ => return Err("RELOAD requires 1 Argument: PERMISSIONS"),
+    }
+}
+
+// LOAD [KEYS] [[VALUES]]
+fn parse_load(parts: Vec<Part>) -> Result<Instruction, &'static str> {
+    let keys: Vec<String>;
+    let values: Vec<Vec<String>>;
+
+    if parts.len() != 3 {
+        return Err("LOAD requires 2 Arguments: <Keys> <<Values>>");
+    }
+
+    load_or_err!(keys, match_into_values(parts.get(1)));
+    load_or_err!(values, match_into_nested(parts.get(2)));
+
+    if keys.len() != values.len() {
+        return Err("Amount of Keys must match amount of values provided.");
+    }
+
+    return Ok(Instruction::Admin(AdminOp::Load(keys, values)));
+}
+
+// FORMAT VALUE
+fn parse_format(parts: Vec<Part>) -> Result<Instruction, &'static str> {
+    let format: String;
+
+    if parts.len() != 2 {
+        return Err("FORMAT requires 1 Argument: <Value>");
+    }
+
+    load_or_err!(format, match_into_value(parts.get(1)));
+
+    return Ok(Instruction::Session(SessionOp::Format(ResponseFormat::from_string(&format)?)));
+}
+
+// AUTH VALUE
+fn parse_auth(parts: Vec<Part>) -> Result<Instruction, &'static str> {
+    let token: String;
+
+    if parts.len() != 2 {
+        return Err("AUTH requires 1 Argument: <Value>");
+    }
+
+    load_or_err!(token, match_into_value(parts.get(1)));
+
+    return Ok(Instruction::Session(SessionOp::Auth(token)));
+}
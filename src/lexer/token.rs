@@ -19,7 +19,7 @@ pub enum Token {
 #[derive(PartialEq, Debug, Clone)]
 pub enum KeywordType {
     Operation, // Get Read etc
-    Instruction, // Transactions
+    Instruction, // Transactions, Lifetimes
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -29,12 +29,14 @@ pub enum Keyword {
     GET,
     EXISTS,
     HAS,
+    META,
     // Write
     PUT,
     DELETE,
     CLEAR,
     REPLACE,
     RETRACT,
+    MOVE,
     // Restricted-Write
     PURGE,
     // Read Write
@@ -44,6 +46,23 @@ pub enum Keyword {
     SEQEUENCE,
     ABORT,
     EXECUTE,
+    // Lifetime
+    WITH,
+    DROP,
+    CREATE,
+    EXPIRE,
+    TAKE,
+    // Admin
+    SNAPSHOT,
+    REWRITE,
+    STATUS,
+    DUMP,
+    LOAD,
+    RELOAD,
+    PERMISSIONS,
+    // Session
+    FORMAT,
+    AUTH,
 }
 
 pub fn get_keyword_token(ident: &Vec<char>) -> Result<Token, String> {
@@ -67,12 +86,14 @@ fn match_keyword(ident: &Vec<char>) -> Result<Keyword, ()> {
         "get" => Ok(Keyword::GET),
         "exists" => Ok(Keyword::EXISTS),
         "has" => Ok(Keyword::HAS),
+        "meta" => Ok(Keyword::META),
         // Write
         "put" => Ok(Keyword::PUT),
         "delete" => Ok(Keyword::DELETE),
         "clear" => Ok(Keyword::CLEAR),
         "replace" => Ok(Keyword::REPLACE),
         "retract" => Ok(Keyword::RETRACT),
+        "move" => Ok(Keyword::MOVE),
         // Restricted
         "purge" => Ok(Keyword::PURGE),
         // Read Write
@@ -90,6 +111,23 @@ fn match_instruction(ident: &Vec<char>) -> Result<Keyword, ()> {
         "sequence" => Ok(Keyword::SEQEUENCE),
         "abort" => Ok(Keyword::ABORT),
         "execute" => Ok(Keyword::EXECUTE),
+        // Lifetimes
+        "with" => Ok(Keyword::WITH),
+        "drop" => Ok(Keyword::DROP),
+        "create" => Ok(Keyword::CREATE),
+        "expire" => Ok(Keyword::EXPIRE),
+        "take" => Ok(Keyword::TAKE),
+        // Admin
+        "snapshot" => Ok(Keyword::SNAPSHOT),
+        "rewrite" => Ok(Keyword::REWRITE),
+        "status" => Ok(Keyword::STATUS),
+        "dump" => Ok(Keyword::DUMP),
+        "load" => Ok(Keyword::LOAD),
+        "reload" => Ok(Keyword::RELOAD),
+        "permissions" => Ok(Keyword::PERMISSIONS),
+        // Session
+        "format" => Ok(Keyword::FORMAT),
+        "auth" => Ok(Keyword::AUTH),
         _ => {Err(())}
     }
 }
@@ -1,26 +1,47 @@
-// use chrono::{DateTime, Local};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
 
-use super::token::{Keyword, Token, KeywordType};
+use std::fmt;
 
-pub fn is_valid(tokens: Vec<Token>) -> Result<Vec<Part>, String> {
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+use super::{lex::quote, token::{Keyword, Token, KeywordType}};
+
+pub fn is_valid(tokens: Vec<Token>) -> Result<Vec<Part>, Error> {
     let mut b = Builder::new();
     for (index, token) in tokens.into_iter().enumerate() {
+        // Tokens are counted from 1
+        if let Token::ILLEGAL(character) = token {
+            return Err(Error::IllegalCharacter { index: index + 1, character });
+        }
+
         match b.transition(token) {
             Ok(_) => {}
             Err(err) => {
-                return Err(format!("{}: {}", index + 1, err));
+                return Err(Error::InvalidToken { index: index + 1, message: err });
             }
         }
     }
+
+    if b.part_buffer.is_empty() {
+        return Err(Error::EmptyCommand);
+    }
     Ok(b.part_buffer)
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Lifetime {
     Static,
-    // Date(DateTime<Local>),
-    User(String),
-    Connection(String)
+    Date(DateTime<Local>),
+    // None binds to the user of the connection that uses the lifetime
+    User(Option<String>),
+    // None binds to the connection that uses the lifetime
+    Connection(Option<String>),
+    // Lives as long as at least one connection is subscribed to the group
+    Linked(String),
+    // Named reference and the lifetime it is bound to, built from [&LIFETIME NAME]
+    Reference(String, Box<Lifetime>),
 }
 
 impl Lifetime {
@@ -30,24 +51,145 @@ impl Lifetime {
                 if value.is_some() { return Err("Static Lifetime does not take Value")}
                 return Ok(Lifetime::Static)
             }
-            "'d" => { todo!() }
-            "'u" => {
+            "'d" => {
                 match value {
-                    Some(user_hash) => { return Ok(Lifetime::User(user_hash))}
-                    None => { return Err("User Lifetime needs User Hash as Value") }
+                    Some(date) => { return Ok(Lifetime::Date(parse_date(&date)?)) }
+                    None => { return Err("Date Lifetime needs Date or Duration as Value") }
                 }
             }
+            "'u" => {
+                return Ok(Lifetime::User(value))
+            }
             "'c" => {
+                return Ok(Lifetime::Connection(value))
+            }
+            "'l" => {
                 match value {
-                    Some(connection_hash) => { return Ok(Lifetime::Connection(connection_hash))}
-                    None => { return Err("Connection Lifetime needs Connection Hash as Value")}
+                    Some(group) => { return Ok(Lifetime::Linked(group)) }
+                    None => { return Err("Linked Lifetime needs Group Name as Value") }
                 }
             }
-            _ => {return Err("")}
+            _ => {return Err("Unknown Lifetime")}
+        }
+    }
+
+    // Binds unbound connection and user lifetimes to the given connection and user
+    pub fn bind(self, connection_id: &str, user: &str) -> Lifetime {
+        match self {
+            Lifetime::Connection(None) => Lifetime::Connection(Some(connection_id.to_string())),
+            Lifetime::User(None) => Lifetime::User(Some(user.to_string())),
+            Lifetime::Reference(name, parent) => Lifetime::Reference(name, Box::new(parent.bind(connection_id, user))),
+            lifetime => lifetime,
+        }
+    }
+
+    // Connection and user lifetimes are owned by the connection, every other lifetime is borrowed
+    pub fn is_owned(&self) -> bool {
+        matches!(self, Lifetime::Connection(_) | Lifetime::User(_))
+    }
+
+    // Lifetime that is not a reference, references are bound to their root
+    pub fn root(&self) -> &Lifetime {
+        match self {
+            Lifetime::Reference(_, parent) => parent.root(),
+            lifetime => lifetime,
+        }
+    }
+
+    // Date the lifetime ends at, references end with the lifetime they are bound to
+    pub fn expiry(&self) -> Option<&DateTime<Local>> {
+        match self {
+            Lifetime::Date(date) => Some(date),
+            Lifetime::Reference(_, parent) => parent.expiry(),
+            _ => None,
+        }
+    }
+}
+
+// Formats the lifetime the way it is written in a command
+impl fmt::Display for Lifetime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lifetime::Static => write!(f, "'s"),
+            Lifetime::Date(date) => write!(f, "'d {}", quote(&date.to_rfc3339())),
+            Lifetime::User(Some(user)) => write!(f, "'u {}", quote(user)),
+            Lifetime::User(None) => write!(f, "'u"),
+            Lifetime::Connection(Some(connection)) => write!(f, "'c {}", quote(connection)),
+            Lifetime::Connection(None) => write!(f, "'c"),
+            Lifetime::Linked(group) => write!(f, "'l {}", quote(group)),
+            Lifetime::Reference(name, parent) => write!(f, "&{} ({})", parent, name),
         }
     }
 }
 
+// Accepts absolute dates ("2024-01-31T12:00:00+01:00", "2024-01-31 12:00:00", "2024-01-31")
+// or durations relative to now ("30s", "+1h30m", "2d")
+fn parse_date(value: &str) -> Result<DateTime<Local>, &'static str> {
+    let date: DateTime<Local>;
+
+    if let Ok(fixed) = DateTime::parse_from_rfc3339(value) {
+        date = fixed.with_timezone(&Local);
+    } else if let Ok(naive) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        let Some(local) = Local.from_local_datetime(&naive).earliest() else { return Err("Date does not exist in the local timezone") };
+        date = local;
+    } else if let Ok(naive_date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let naive = naive_date.and_hms_opt(0, 0, 0).unwrap();
+        let Some(local) = Local.from_local_datetime(&naive).earliest() else { return Err("Date does not exist in the local timezone") };
+        date = local;
+    } else {
+        match Local::now().checked_add_signed(parse_duration(value)?) {
+            Some(local) => date = local,
+            None => return Err("Duration is too long"),
+        }
+    }
+
+    if date <= Local::now() {
+        return Err("Date Lifetime lies in the past");
+    }
+
+    Ok(date)
+}
+
+fn parse_duration(value: &str) -> Result<Duration, &'static str> {
+    let value = value.strip_prefix('+').unwrap_or(value);
+    let mut seconds: i64 = 0;
+    let mut number: Option<i64> = None;
+
+    for c in value.chars() {
+        if let Some(digit) = c.to_digit(10) {
+            number = number.unwrap_or(0).checked_mul(10).and_then(|n| n.checked_add(digit as i64));
+            if number.is_none() {
+                return Err("Duration is too long");
+            }
+            continue;
+        }
+
+        let Some(amount) = number.take() else { return Err("Duration unit without amount") };
+        let unit: i64 = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err("Invalid Date or Duration, units are s, m, h and d"),
+        };
+        match amount.checked_mul(unit).and_then(|n| n.checked_add(seconds)) {
+            Some(total) => seconds = total,
+            None => return Err("Duration is too long"),
+        }
+    }
+
+    if number.is_some() || value.is_empty() {
+        return Err("Invalid Date or Duration, units are s, m, h and d");
+    }
+
+    // Larger durations can not be represented by chrono
+    if seconds > i64::MAX / 1000 {
+        return Err("Duration is too long");
+    }
+
+    Ok(Duration::seconds(seconds))
+}
+
 #[derive(Debug)]
 pub enum Part {
     Keyword {
@@ -81,6 +223,8 @@ enum State {
     LIFETIME,
     FILLEDLIFETIME,
     NAMEDLIFETIME,
+    OPENNAMEDLIFETIME,
+    CLOSENAMEDLIFETIME,
 }
 
 #[derive(Debug)]
@@ -121,6 +265,9 @@ impl Builder<State, Context> {
         self.context = Context::DEFAULT;
         self.values_buffer.clear();
         self.nested_values_buffer.clear();
+        self.lifetime_reference_name_buffer = None;
+        self.lifetime_name_buffer = None;
+        self.lifetime_value_buffer = None;
     }
 
     fn construct_lifetime(&mut self) -> Result<(), &'static str> {
@@ -299,6 +446,9 @@ impl Builder<State, Context> {
                         self.lifetime_reference_name_buffer = Some(ident.iter().collect());
                         self.state = State::NAMEDLIFETIME;
                     }
+                    Token::LPAREN => {
+                        self.state = State::OPENNAMEDLIFETIME;
+                    }
                     err_token => return Err(format!("Invalid Token {:?} after Lifetime", err_token)),
                 }
                 Ok(())
@@ -310,6 +460,9 @@ impl Builder<State, Context> {
                         self.lifetime_reference_name_buffer = Some(ident.iter().collect());
                         self.state = State::NAMEDLIFETIME;
                     }
+                    Token::LPAREN => {
+                        self.state = State::OPENNAMEDLIFETIME;
+                    }
                     Token::RBRACE => match c {
                         Context::DEFAULT => {
                             match self.construct_lifetime() {
@@ -338,6 +491,26 @@ impl Builder<State, Context> {
                 }
                 Ok(())
             } 
+
+            // Name of the lifetime in parenthesis [&'s (A)]
+            (State::OPENNAMEDLIFETIME, _) => {
+                match next_token {
+                    Token::IDENT(ident) => {
+                        self.lifetime_reference_name_buffer = Some(ident.iter().collect());
+                        self.state = State::CLOSENAMEDLIFETIME;
+                    }
+                    err_token => {return Err(format!("Invalid Token {:?} after Open Named Lifetime", err_token));}
+                }
+                Ok(())
+            }
+
+            (State::CLOSENAMEDLIFETIME, _) => {
+                match next_token {
+                    Token::RPAREN => self.state = State::NAMEDLIFETIME,
+                    err_token => {return Err(format!("Invalid Token {:?} after Close Named Lifetime", err_token));}
+                }
+                Ok(())
+            }
             (s, c) => return Err(format!("Invalid State {:?} with context {:?}", s, c)),
         }
     }
@@ -1,17 +1,33 @@
 mod connection;
 mod dbop;
+mod error;
 mod lexer;
+mod persistence;
+mod transfer;
 
-use clap::{Parser, ValueEnum};
-use connection::connection::handle_connection;
+use clap::{Parser, Subcommand, ValueEnum};
+use connection::connection::{handle_connection, ConnectionSettings, DEFAULT_MAX_FRAME_LENGTH};
+use dbop::{kernel::Kernel, reaper::spawn_reaper};
 use evmap::ReadHandleFactory;
+use persistence::{
+    replay::replay,
+    snapshot::{spawn_snapshotter, SnapshotStore},
+    write_log::{spawn_log_syncer, Fsync, WriteLog},
+};
 use std::{
+    fmt::Display,
     net::TcpListener,
+    process,
     sync::{Arc, Mutex},
     thread,
+    time::Duration,
 };
 
-use crate::connection::{permission::Permission, permission_list::{PermissionState, PermissionList}};
+use crate::connection::{
+    cidr::{is_allowed, Cidr},
+    permission_list::{spawn_permission_watcher, PermissionList, SharedPermissions},
+};
+use crate::transfer::{export::export, import::import, TransferArgs};
 
 #[derive(ValueEnum, Debug, Clone)] // ArgEnum here
 #[clap(rename_all = "kebab_case")]
@@ -23,7 +39,7 @@ enum Mode {
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    // Address under which the TcpListener will be bound
+    /// Address under which the TcpListener will be bound
     #[arg(short, long, default_value_t = String::from("127.0.0.1:3254"))]
     address: String,
 
@@ -31,52 +47,181 @@ struct Args {
     mode: Mode,
 
     #[arg(short, long)]
-    perm_path: Option<String>
+    perm_path: Option<String>,
+
+    /// Connections have to AUTH with a token of the permission file before anything else
+    #[arg(long)]
+    require_auth: bool,
+
+    /// Connections are closed once their token was removed from the reloaded permission file
+    #[arg(long, requires = "perm_path")]
+    disconnect_revoked: bool,
+
+    /// Networks that may connect, like 10.0.0.0/8, can be given multiple times. Without it everyone may connect
+    #[arg(long)]
+    allow_ip: Vec<Cidr>,
+
+    /// Append only log of every change, replayed on startup
+    #[arg(short, long)]
+    log_path: Option<String>,
+
+    /// How often the write log is synced to disk
+    #[arg(short, long, value_enum, default_value_t = Fsync::EverySecond)]
+    fsync: Fsync,
+
+    /// Directory for snapshots, the newest one is loaded on startup
+    #[arg(short, long)]
+    snapshot_dir: Option<String>,
+
+    /// Seconds between two automatic snapshots
+    #[arg(long, requires = "snapshot_dir")]
+    snapshot_interval: Option<u64>,
+
+    /// Largest frame in bytes a length prefixed connection may send
+    #[arg(long, default_value_t = DEFAULT_MAX_FRAME_LENGTH)]
+    max_frame_length: usize,
+
+    /// Runs a tool against the server at address instead of starting a server
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Writes every key of the server to a file
+    Export(TransferArgs),
+    /// Loads every key of a file into the server, replacing the values of existing keys
+    Import(TransferArgs),
+}
+
+fn run_command(address: &str, command: &Command) {
+    let result = match command {
+        Command::Export(args) => export(address, args).map(|keys| format!("Exported {} keys to {}", keys, args.file)),
+        Command::Import(args) => import(address, args).map(|keys| format!("Imported {} keys from {}", keys, args.file)),
+    };
+
+    match result {
+        Ok(message) => println!("{}", message),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+// Startup can not continue without its files, so the error is reported and the server exits
+fn or_exit<T>(result: Result<T, impl Display>, context: &str) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("{}: {}", context, err);
+            process::exit(1);
+        }
+    }
 }
 
 fn main() {
     // Init the kernel section
     let (read, write) = evmap::new();
+    let (expiry_read, expiry_write) = evmap::new();
     let read_factory: ReadHandleFactory<String, String> = read.factory();
-    let read_mutex = Arc::new(Mutex::new(write));
+    let expiry_factory: ReadHandleFactory<String, i64> = expiry_read.factory();
+    let read_mutex = Arc::new(Mutex::new(Kernel::new(write, expiry_write)));
 
     let command_line_args = Args::parse();
+    if let Some(command) = &command_line_args.command {
+        run_command(&command_line_args.address, command);
+        return;
+    }
+
+    // Restore the state from the newest snapshot and the write log before anything else changes it
+    {
+        let mut kernel = read_mutex.lock().unwrap();
+        let mut seq: Option<u64> = None;
+
+        if let Some(snapshot_dir) = &command_line_args.snapshot_dir {
+            let store = or_exit(SnapshotStore::open(snapshot_dir), &format!("Could not open snapshot dir {}", snapshot_dir));
+            if let Some(snapshot) = or_exit(store.newest(), &format!("Could not read snapshot dir {}", snapshot_dir)) {
+                println!("Loaded snapshot of {} keys from {}", snapshot.keys.len(), snapshot.time);
+                seq = Some(snapshot.seq);
+                snapshot.restore(&mut kernel);
+            }
+            kernel.snapshots = Some(store);
+        }
+
+        if let Some(log_path) = &command_line_args.log_path {
+            let (replayed, last_seq) = or_exit(replay(&mut kernel, log_path, seq), &format!("Could not replay {}", log_path));
+            println!("Replayed {} entries from {}", replayed, log_path);
+
+            let log = WriteLog::open(log_path, command_line_args.fsync.clone(), last_seq);
+            kernel.log = Some(or_exit(log, &format!("Could not open {}", log_path)));
+            if command_line_args.fsync == Fsync::EverySecond {
+                spawn_log_syncer(Arc::clone(&read_mutex));
+            }
+        }
+
+        kernel.expire_sessions();
+        kernel.refresh();
+    }
+
+    if let Some(interval) = command_line_args.snapshot_interval {
+        spawn_snapshotter(Arc::clone(&read_mutex), Duration::from_secs(interval));
+    }
+
+    // Removes keys whose date lifetime ran out
+    spawn_reaper(Arc::clone(&read_mutex));
+
+    let permission_list = match &command_line_args.perm_path {
+        Some(path) => match PermissionList::try_from_path(path) {
+            Ok(permission_list) => permission_list,
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        },
+        None => PermissionList::default(),
+    };
+    println!("Permissions: {:?}", permission_list.names());
+
+    // The file is watched for changes, RELOAD PERMISSIONS reloads it right away
+    let permissions = Arc::new(SharedPermissions::new(permission_list, command_line_args.perm_path.clone()));
+    if command_line_args.perm_path.is_some() {
+        spawn_permission_watcher(Arc::clone(&permissions));
+    }
+
     let listener = TcpListener::bind(&command_line_args.address).unwrap();
     println!(
         "Clavrs is running at {} in {:?}-Mode",
         &command_line_args.address, &command_line_args.mode
     );
 
-    let permissions: PermissionList = PermissionList::from_path(command_line_args.perm_path);
-
-    println!("{}", serde_json::to_string(&permissions).unwrap());
-
-    for stream in listener.incoming() {
+    let settings = ConnectionSettings {
+        mode: command_line_args.mode.clone(),
+        require_auth: command_line_args.require_auth,
+        disconnect_revoked: command_line_args.disconnect_revoked,
+        max_frame_length: command_line_args.max_frame_length,
+    };
 
-        // TODO
-        // Permission could be checked here, and loaded on updated file
+    // Failed accepts are skipped
+    for stream in listener.incoming().flatten() {
+        // Peers outside of the allowed networks are closed before anything is read
+        let Ok(peer) = stream.peer_addr() else { continue };
+        if !is_allowed(&command_line_args.allow_ip, &[], &peer.ip()) {
+            println!("{:?}: Connection refused, address is not allowed", peer);
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+            continue;
+        }
 
-        match stream {
-            Ok(stream) => {
-                let read_handle = read_factory.handle();
-                let write_mutex = Arc::clone(&read_mutex);
+        let read_handle = read_factory.handle();
+        let expiry_handle = expiry_factory.handle();
+        let write_mutex = Arc::clone(&read_mutex);
 
-                println!("{:?}: Connection Established", stream.peer_addr().unwrap());
+        println!("{:?}: Connection Established", peer);
 
-                let db_mode = command_line_args.mode.clone();
-                thread::spawn(|| {
-                    // TODO
-                    // authenticate connection first.
-                    // if auth fail just close connection
-                    handle_connection(
-                        stream,
-                        read_handle,
-                        write_mutex,
-                        Permission::new(&PermissionState::default(), db_mode),
-                    );
-                });
-            }
-            Err(_) => {}
-        }
+        let permissions = Arc::clone(&permissions);
+        let settings = settings.clone();
+        thread::spawn(move || {
+            handle_connection(stream, read_handle, expiry_handle, write_mutex, permissions, settings);
+        });
     }
 }
@@ -0,0 +1,5 @@
+pub mod record;
+pub mod replay;
+pub mod rewrite;
+pub mod snapshot;
+pub mod write_log;
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dbop::{
+        op_type::{Op, ReadWriteOp, WriteOp},
+        origin::Origin,
+    },
+    lexer::validate::Lifetime,
+};
+
+use super::snapshot::KeySnapshot;
+
+// Every change of the kernel that has to survive a restart
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Record {
+    Write(WriteOp),
+    ReadWrite(ReadWriteOp),
+    // Operations of a transaction that were applied, in order
+    Transaction(Vec<Op>),
+    Create(Lifetime),
+    Drop(Lifetime),
+    Expire(Lifetime),
+    // Written by a log rewrite, which starts from nothing and sets every key to its state
+    Reset,
+    Restore(KeySnapshot),
+    References(HashMap<String, Lifetime>),
+    // Creators of the references, written after References
+    Creators(HashMap<String, String>),
+}
+
+// A single line in the write log
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    // Position in the log, snapshots remember up to which entry they contain the changes
+    #[serde(default)]
+    pub seq: u64,
+    pub origin: Origin,
+    pub record: Record,
+}
+
+impl Entry {
+    // A single line of the write log
+    pub fn encode(&self) -> serde_json::Result<String> {
+        let mut line = serde_json::to_string(self)?;
+        line.push('\n');
+        Ok(line)
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, BufRead, BufReader},
+};
+
+use crate::dbop::{
+    kernel::Kernel, op_type::Op, read_write::execute_read_write, write::execute_write,
+};
+
+use super::record::{Entry, Record};
+
+// Applies a logged record to the kernel, errors were already reported when the record was logged
+pub fn apply(kernel: &mut Kernel, entry: Entry) {
+    let origin = entry.origin;
+
+    match entry.record {
+        Record::Write(write_op) => {
+            let _ = execute_write(kernel, write_op, &origin);
+        }
+        Record::ReadWrite(readwrite_op) => {
+            let _ = execute_read_write(kernel, readwrite_op, &origin);
+        }
+        Record::Transaction(operations) => {
+            for operation in operations {
+                match operation {
+                    Op::Write(write_op) => {
+                        let _ = execute_write(kernel, write_op, &origin);
+                    }
+                    Op::ReadWrite(readwrite_op) => {
+                        let _ = execute_read_write(kernel, readwrite_op, &origin);
+                    }
+                    Op::Read(_) => {}
+                }
+            }
+        }
+        Record::Create(lifetime) => {
+            let _ = kernel.create_reference(&lifetime, &origin.name);
+        }
+        Record::Drop(lifetime) => {
+            kernel.drop_lifetime(&lifetime);
+        }
+        Record::Expire(lifetime) => {
+            kernel.expire_lifetime(&lifetime);
+        }
+        Record::Reset => {
+            kernel.lifetimes.clear();
+            kernel.meta.clear();
+            kernel.write_handle.purge();
+            kernel.references.clear();
+            kernel.creators.clear();
+        }
+        Record::Restore(key_snapshot) => {
+            key_snapshot.restore(kernel);
+        }
+        Record::References(references) => {
+            kernel.references = references;
+        }
+        Record::Creators(creators) => {
+            kernel.creators = creators;
+        }
+    }
+}
+
+// Replays the entries of the log at path into the kernel, with a snapshot only the entries after its seq
+// Returns the amount of replayed entries and the sequence number of the last entry in the log
+// A torn last line from a crash is cut off, so new entries are not appended to it
+pub fn replay(kernel: &mut Kernel, path: &str, snapshot_seq: Option<u64>) -> io::Result<(usize, u64)> {
+    let file = match OpenOptions::new().read(true).open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok((0, snapshot_seq.unwrap_or(0))),
+        Err(err) => return Err(err),
+    };
+
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    let mut valid_length: u64 = 0;
+    let mut replayed: usize = 0;
+    let mut last_seq = snapshot_seq.unwrap_or(0);
+
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 || !line.ends_with('\n') {
+            break;
+        }
+
+        let Ok(entry) = serde_json::from_str::<Entry>(&line) else { break };
+        valid_length += read as u64;
+
+        // The snapshot already contains the change
+        // Entries of logs from before sequence numbers have seq 0, they are older than every snapshot
+        if snapshot_seq.is_some_and(|seq| entry.seq <= seq) {
+            continue;
+        }
+        last_seq = last_seq.max(entry.seq);
+        apply(kernel, entry);
+        replayed += 1;
+    }
+
+    if fs::metadata(path)?.len() > valid_length {
+        println!("Write log {} is torn after byte {}, cutting it off", path, valid_length);
+        OpenOptions::new().write(true).open(path)?.set_len(valid_length)?;
+    }
+
+    kernel.refresh();
+    Ok((replayed, last_seq))
+}
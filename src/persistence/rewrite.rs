@@ -0,0 +1,218 @@
+use std::{
+    any::Any,
+    fs::{self, File},
+    io::{self, Write},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use chrono::{DateTime, Local};
+use evmap::ReadHandle;
+
+use crate::dbop::{kernel::Kernel, op_type::WriteOp, origin::Origin, response::Response};
+
+use super::{
+    record::{Entry, Record},
+    snapshot::KeySnapshot,
+};
+
+// Amount of keys whose lifetime and meta data are read per lock of the kernel
+pub const REWRITE_BATCH: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub enum RewriteStatus {
+    Idle,
+    Running {
+        started: DateTime<Local>,
+        written: usize,
+        total: usize,
+    },
+    Finished {
+        finished: DateTime<Local>,
+        keys: usize,
+        bytes: u64,
+    },
+    Failed(String),
+}
+
+impl RewriteStatus {
+    pub fn describe(&self) -> String {
+        match self {
+            RewriteStatus::Idle => "Idle".to_string(),
+            RewriteStatus::Running { started, written, total } => {
+                format!("Running {}/{} keys since {}", written, total, started.to_rfc3339())
+            }
+            RewriteStatus::Finished { finished, keys, bytes } => {
+                format!("Finished {} keys in {} bytes at {}", keys, bytes, finished.to_rfc3339())
+            }
+            RewriteStatus::Failed(err) => format!("Failed {}", err),
+        }
+    }
+}
+
+// Starts rewriting the write log into the smallest log that gives the current state
+pub fn start_rewrite(
+    write_mutex: &Arc<Mutex<Kernel>>,
+    read_handle: &ReadHandle<String, String>,
+) -> Result<Response, String> {
+    let mut kernel = write_mutex.lock().unwrap();
+
+    if let RewriteStatus::Running { .. } = kernel.rewrite {
+        return Err("Rewrite is already running".to_string());
+    }
+    let Some(log) = kernel.log.as_ref() else {
+        return Err("Write log is not enabled".to_string());
+    };
+
+    // Keys changed from here on are written again with their final state
+    let seq = log.seq();
+    let mut rewritten = log.path().as_os_str().to_owned();
+    rewritten.push(".rewrite");
+    kernel.meta.track_changes();
+    kernel.rewrite = RewriteStatus::Running {
+        started: Local::now(),
+        written: 0,
+        total: 0,
+    };
+    drop(kernel);
+
+    let write_mutex = Arc::clone(write_mutex);
+    let read_handle = read_handle.clone();
+    thread::spawn(move || {
+        let path = PathBuf::from(rewritten);
+        // A panic must not leave the rewrite running and the changes tracked forever
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| rewrite(&write_mutex, &read_handle, &path, seq))) {
+            Ok(result) => result.map_err(|err| err.to_string()),
+            Err(panic) => Err(panic_message(&panic)),
+        };
+
+        let mut kernel = match write_mutex.lock() {
+            Ok(kernel) => kernel,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        kernel.rewrite = match result {
+            Ok((keys, bytes)) => RewriteStatus::Finished {
+                finished: Local::now(),
+                keys,
+                bytes,
+            },
+            Err(err) => {
+                println!("Failed to rewrite write log: {}", err);
+                kernel.meta.take_changes();
+                let _ = fs::remove_file(&path);
+                RewriteStatus::Failed(err)
+            }
+        };
+    });
+
+    Ok(Response::Ok)
+}
+
+fn panic_message(panic: &Box<dyn Any + Send>) -> String {
+    match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(message), _) => format!("Rewrite panicked: {}", message),
+        (_, Some(message)) => format!("Rewrite panicked: {}", message),
+        _ => "Rewrite panicked".to_string(),
+    }
+}
+
+// Runs f with the kernel locked, a panic is returned as error so it does not poison the kernel for everyone else
+fn with_kernel<T>(write_mutex: &Arc<Mutex<Kernel>>, f: impl FnOnce(&mut Kernel) -> io::Result<T>) -> io::Result<T> {
+    let mut kernel = write_mutex.lock().unwrap();
+    match panic::catch_unwind(AssertUnwindSafe(|| f(&mut kernel))) {
+        Ok(result) => result,
+        Err(panic) => Err(io::Error::other(panic_message(&panic))),
+    }
+}
+
+pub fn rewrite_status(write_mutex: &Arc<Mutex<Kernel>>) -> Result<Response, String> {
+    let kernel = write_mutex.lock().unwrap();
+    Ok(Response::Text(kernel.rewrite.describe()))
+}
+
+fn write_record(file: &mut File, seq: u64, record: Record) -> io::Result<u64> {
+    let entry = Entry {
+        seq,
+        origin: Origin::server(),
+        record,
+    };
+    let line = entry.encode()?;
+    file.write_all(line.as_bytes())?;
+    Ok(line.len() as u64)
+}
+
+// Scans the read side without holding the lock, only the keys changed meanwhile are written under the lock
+// Records of the scan keep the sequence number the rewrite started at, so snapshots taken meanwhile still apply
+fn rewrite(
+    write_mutex: &Arc<Mutex<Kernel>>,
+    read_handle: &ReadHandle<String, String>,
+    path: &PathBuf,
+    seq: u64,
+) -> io::Result<(usize, u64)> {
+    let mut file = File::create(path)?;
+    let mut bytes = write_record(&mut file, seq, Record::Reset)?;
+
+    let keys: Vec<String> = match read_handle.read() {
+        Some(map) => map.iter().map(|(key, _)| key.clone()).collect(),
+        None => vec![],
+    };
+
+    let mut written: usize = 0;
+    for batch in keys.chunks(REWRITE_BATCH) {
+        let values: Vec<Option<Vec<String>>> = batch
+            .iter()
+            .map(|key| read_handle.get(key).map(|values| values.iter().cloned().collect()))
+            .collect();
+
+        written += batch.len();
+        let snapshots: Vec<KeySnapshot> = with_kernel(write_mutex, |kernel| {
+            kernel.rewrite = match kernel.rewrite {
+                RewriteStatus::Running { started, .. } => RewriteStatus::Running {
+                    started,
+                    written,
+                    total: keys.len(),
+                },
+                ref status => status.clone(),
+            };
+
+            Ok(batch
+                .iter()
+                .zip(values)
+                .filter_map(|(key, values)| Some(KeySnapshot::capture(kernel, key, values?)))
+                .collect())
+        })?;
+
+        for snapshot in snapshots {
+            bytes += write_record(&mut file, seq, Record::Restore(snapshot))?;
+        }
+    }
+
+    // Swapping the log blocks writers, but only for the keys that changed during the scan
+    with_kernel(write_mutex, |kernel| {
+        let seq = kernel.log.as_ref().map_or(seq, |log| log.seq());
+        let changed = kernel.meta.take_changes();
+
+        for key in changed.iter() {
+            let record = match kernel.write_handle.get(key) {
+                Some(values) => {
+                    let values = values.iter().cloned().collect();
+                    Record::Restore(KeySnapshot::capture(kernel, key, values))
+                }
+                None => Record::Write(WriteOp::Delete(vec![key.clone()])),
+            };
+            bytes += write_record(&mut file, seq, record)?;
+        }
+        bytes += write_record(&mut file, seq, Record::References(kernel.references.clone()))?;
+        bytes += write_record(&mut file, seq, Record::Creators(kernel.creators.clone()))?;
+        file.sync_all()?;
+
+        match kernel.log.as_mut() {
+            Some(log) => log.replace(path)?,
+            None => return Err(io::Error::other("Write log was disabled")),
+        }
+
+        Ok((keys.len(), bytes))
+    })
+}
@@ -0,0 +1,189 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dbop::{kernel::Kernel, meta_map::Meta, response::Response},
+    lexer::validate::Lifetime,
+};
+
+// Amount of snapshots that are kept, older ones are removed
+pub const SNAPSHOT_RETAIN: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeySnapshot {
+    pub key: String,
+    pub values: Vec<String>,
+    pub lifetime: Lifetime,
+    pub meta: Option<Meta>,
+}
+
+impl KeySnapshot {
+    // Has to be called with the kernel locked, the values may be read from any read handle
+    pub fn capture(kernel: &Kernel, key: &str, values: Vec<String>) -> Self {
+        KeySnapshot {
+            key: key.to_string(),
+            values,
+            lifetime: kernel.lifetimes.get(key).cloned().unwrap_or(Lifetime::Static),
+            meta: kernel.meta.get(key).cloned(),
+        }
+    }
+
+    // Sets the key to the captured state, whatever state it had before
+    pub fn restore(self, kernel: &mut Kernel) {
+        kernel.write_handle.clear(self.key.clone());
+        for value in self.values {
+            kernel.write_handle.insert(self.key.clone(), value);
+        }
+        kernel.lifetimes.bind(&self.key, self.lifetime);
+        match self.meta {
+            Some(meta) => kernel.meta.insert(&self.key, meta),
+            None => kernel.meta.remove(&self.key),
+        }
+    }
+}
+
+// The whole keyspace at one point in time
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Snapshot {
+    // Sequence number of the last log entry whose change is contained
+    pub seq: u64,
+    pub time: DateTime<Local>,
+    pub keys: Vec<KeySnapshot>,
+    pub references: HashMap<String, Lifetime>,
+    #[serde(default)]
+    pub creators: HashMap<String, String>,
+}
+
+impl Snapshot {
+    // Has to be called with the kernel locked, so no change is half contained
+    pub fn capture(kernel: &Kernel) -> Self {
+        let mut keys: Vec<KeySnapshot> = vec![];
+
+        if let Some(map) = kernel.write_handle.read() {
+            for (key, values) in map.iter() {
+                keys.push(KeySnapshot::capture(kernel, key, values.iter().cloned().collect()));
+            }
+        }
+
+        Snapshot {
+            seq: kernel.log.as_ref().map_or(0, |log| log.seq()),
+            time: Local::now(),
+            keys,
+            references: kernel.references.clone(),
+            creators: kernel.creators.clone(),
+        }
+    }
+
+    // Loads the snapshot into an empty kernel
+    pub fn restore(self, kernel: &mut Kernel) {
+        for entry in self.keys {
+            entry.restore(kernel);
+        }
+        kernel.references.extend(self.references);
+        kernel.creators.extend(self.creators);
+        kernel.refresh();
+    }
+}
+
+// Directory the snapshots are written to, files are named after their sequence number and time
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl SnapshotStore {
+    pub fn open(dir: &str) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(SnapshotStore { dir: PathBuf::from(dir) })
+    }
+
+    // Writes to a temporary file first, so a crash never leaves a partial snapshot behind
+    pub fn write(&self, snapshot: &Snapshot) -> io::Result<PathBuf> {
+        let name = format!("snapshot-{:020}-{:016}.json", snapshot.seq, snapshot.time.timestamp_millis());
+        let path = self.dir.join(&name);
+        let temp_path = self.dir.join(format!(".{}.tmp", name));
+
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&serde_json::to_vec(snapshot)?)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, &path)?;
+        File::open(&self.dir)?.sync_all()?;
+
+        self.prune()?;
+        Ok(path)
+    }
+
+    // Snapshots that can not be read are skipped, the older ones are still complete
+    pub fn newest(&self) -> io::Result<Option<Snapshot>> {
+        for path in self.list()?.into_iter().rev() {
+            let snapshot = fs::read(&path).and_then(|bytes| Ok(serde_json::from_slice::<Snapshot>(&bytes)?));
+            match snapshot {
+                Ok(snapshot) => return Ok(Some(snapshot)),
+                Err(err) => println!("Skipping snapshot {}: {}", path.display(), err),
+            }
+        }
+        Ok(None)
+    }
+
+    // Snapshot files from oldest to newest
+    fn list(&self) -> io::Result<Vec<PathBuf>> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+                name.starts_with("snapshot-") && name.ends_with(".json")
+            })
+            .collect();
+        paths.sort();
+        Ok(paths)
+    }
+
+    fn prune(&self) -> io::Result<()> {
+        let paths = self.list()?;
+        let outdated = paths.len().saturating_sub(SNAPSHOT_RETAIN);
+        for path in paths.into_iter().take(outdated) {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+// Captures the keyspace under the lock, but writes it to disk without holding the lock
+pub fn take_snapshot(write_mutex: &Arc<Mutex<Kernel>>) -> Result<Response, String> {
+    let (snapshot, store) = {
+        let kernel = write_mutex.lock().unwrap();
+        let Some(store) = kernel.snapshots.clone() else {
+            return Err("Snapshots are not enabled".to_string());
+        };
+        (Snapshot::capture(&kernel), store)
+    };
+
+    match store.write(&snapshot) {
+        Ok(_) => Ok(Response::Ok),
+        Err(err) => {
+            println!("Failed to write snapshot: {:?}", err);
+            Err("Snapshot could not be written".to_string())
+        }
+    }
+}
+
+// Takes a snapshot every interval
+pub fn spawn_snapshotter(write_mutex: Arc<Mutex<Kernel>>, interval: Duration) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(interval);
+
+        // Failures are reported by take_snapshot, the next interval tries again
+        let _ = take_snapshot(&write_mutex);
+    })
+}
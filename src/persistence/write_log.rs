@@ -0,0 +1,111 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use clap::ValueEnum;
+
+use crate::dbop::{kernel::Kernel, origin::Origin};
+
+use super::record::{Entry, Record};
+
+// Time between two syncs with Fsync::EverySecond
+pub const SYNC_INTERVAL: Duration = Duration::new(1, 0);
+
+#[derive(ValueEnum, Debug, Clone, PartialEq)]
+#[clap(rename_all = "kebab_case")]
+pub enum Fsync {
+    /// Sync after every record, nothing acknowledged is lost
+    Always,
+    /// Sync once a second, at most one second of records is lost on power loss
+    EverySecond,
+    /// Leave syncing to the operating system
+    Never,
+}
+
+// Append only log of every change, one json encoded Entry per line
+pub struct WriteLog {
+    path: PathBuf,
+    file: File,
+    fsync: Fsync,
+    unsynced: bool,
+    // Sequence number of the last appended entry
+    seq: u64,
+}
+
+impl WriteLog {
+    // Appended entries continue after seq
+    pub fn open(path: &str, fsync: Fsync, seq: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(WriteLog {
+            path: PathBuf::from(path),
+            file,
+            fsync,
+            unsynced: false,
+            seq,
+        })
+    }
+
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Swaps the log for the rewritten one, new entries are appended to it
+    pub fn replace(&mut self, rewritten: &Path) -> io::Result<()> {
+        fs::rename(rewritten, &self.path)?;
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.unsynced = false;
+        Ok(())
+    }
+
+    pub fn append(&mut self, origin: &Origin, record: Record) -> io::Result<()> {
+        let entry = Entry {
+            seq: self.seq + 1,
+            origin: origin.clone(),
+            record,
+        };
+        let line = entry.encode()?;
+        // A single write, so a crash can only cut off the last line
+        self.file.write_all(line.as_bytes())?;
+
+        match self.fsync {
+            Fsync::Always => self.file.sync_data()?,
+            _ => self.unsynced = true,
+        }
+        self.seq = entry.seq;
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        if self.unsynced {
+            self.file.sync_data()?;
+            self.unsynced = false;
+        }
+        Ok(())
+    }
+}
+
+// Syncs the log of the kernel every SYNC_INTERVAL
+pub fn spawn_log_syncer(write_mutex: Arc<Mutex<Kernel>>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(SYNC_INTERVAL);
+
+        let mut kernel = write_mutex.lock().unwrap();
+        if let Some(log) = kernel.log.as_mut() {
+            if let Err(err) = log.sync() {
+                println!("Failed to sync write log: {:?}", err);
+            }
+        }
+    })
+}
@@ -0,0 +1,59 @@
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+use crate::{
+    connection::read_all_from_stream::{Framing, FRAMED_HANDSHAKE, FRAME_HEADER_LENGTH},
+    dbop::response::ERR_PREFIX,
+    lexer::lex::quote,
+};
+
+fn read_error(err: io::Error) -> String {
+    match err.kind() {
+        ErrorKind::UnexpectedEof => "Connection was closed by the server".to_string(),
+        _ => err.to_string(),
+    }
+}
+
+// Minimal client for the import and export commands
+// Messages are length prefixed, so keys and values may be of any length and contain any character
+pub struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    // Authenticates with the token first, if one is given
+    pub fn connect(address: &str, token: Option<&str>) -> Result<Self, String> {
+        let mut client = match TcpStream::connect(address) {
+            Ok(stream) => Client { stream },
+            Err(err) => return Err(format!("Could not connect to {}: {}", address, err)),
+        };
+        client.stream.write_all(&[FRAMED_HANDSHAKE]).map_err(|err| err.to_string())?;
+
+        if let Some(token) = token {
+            client.send(&format!("AUTH {}", quote(token))).map_err(|err| format!("Could not authenticate: {}", err))?;
+        }
+        Ok(client)
+    }
+
+    fn into_result(response: String) -> Result<String, String> {
+        match response.strip_prefix(ERR_PREFIX) {
+            Some(err) => Err(err.to_string()),
+            None => Ok(response),
+        }
+    }
+
+    // Responses are framed like the requests
+    pub fn send(&mut self, command: &str) -> Result<String, String> {
+        self.stream.write_all(&Framing::LengthPrefixed.frame(command)).map_err(|err| err.to_string())?;
+
+        let mut header = [0; FRAME_HEADER_LENGTH];
+        self.stream.read_exact(&mut header).map_err(read_error)?;
+        let mut response = vec![0; u32::from_be_bytes(header) as usize];
+        self.stream.read_exact(&mut response).map_err(read_error)?;
+
+        let response = String::from_utf8(response).map_err(|_| "Response is not valid utf8".to_string())?;
+        Client::into_result(response)
+    }
+}
@@ -0,0 +1,78 @@
+use super::Multimap;
+
+fn encode_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// One row per key, the key followed by its values
+pub fn encode(map: &Multimap) -> String {
+    let mut csv = String::new();
+    for (key, values) in map.iter() {
+        let row: Vec<String> = std::iter::once(key)
+            .chain(values.iter())
+            .map(|field| encode_field(field))
+            .collect();
+        csv += &row.join(",");
+        csv += "\n";
+    }
+    csv
+}
+
+// Rows of the same key are merged, empty lines are skipped
+pub fn decode(csv: &str) -> Result<Multimap, String> {
+    let mut map = Multimap::new();
+    let mut row: Vec<String> = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line: usize = 1;
+    let mut chars = csv.chars().peekable();
+
+    let mut end_row = |row: &mut Vec<String>| {
+        if row.len() == 1 && row[0].is_empty() {
+            row.clear();
+            return;
+        }
+        let mut fields = row.drain(..);
+        if let Some(key) = fields.next() {
+            map.entry(key).or_default().extend(fields);
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            ('"', false) => return Err(format!("Line {}: Quote in the middle of an unquoted field", line)),
+            (',', false) => row.push(std::mem::take(&mut field)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n', false) => {
+                row.push(std::mem::take(&mut field));
+                end_row(&mut row);
+                line += 1;
+            }
+            ('\n', true) => {
+                field.push(c);
+                line += 1;
+            }
+            (c, _) => field.push(c),
+        }
+    }
+
+    if quoted {
+        return Err(format!("Line {}: Quoted field is not closed", line));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        end_row(&mut row);
+    }
+
+    Ok(map)
+}
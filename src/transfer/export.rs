@@ -0,0 +1,17 @@
+use std::fs;
+
+use super::{client::Client, csv, Format, Multimap, TransferArgs};
+
+// Writes every key of the server to the file and returns the amount of keys
+pub fn export(address: &str, args: &TransferArgs) -> Result<usize, String> {
+    let dump = Client::connect(address, args.token.as_deref())?.send("DUMP")?;
+    let map: Multimap = serde_json::from_str(&dump).map_err(|err| format!("Invalid DUMP response: {}", err))?;
+
+    let content = match args.format {
+        Format::Json => serde_json::to_string_pretty(&map).map_err(|err| err.to_string())?,
+        Format::Csv => csv::encode(&map),
+    };
+
+    fs::write(&args.file, content).map_err(|err| format!("Could not write {}: {}", args.file, err))?;
+    Ok(map.len())
+}
@@ -0,0 +1,70 @@
+use std::{fs, slice};
+
+use crate::{connection::connection::DEFAULT_MAX_FRAME_LENGTH, lexer::lex::quote};
+
+use super::{client::Client, csv, Format, Multimap, TransferArgs};
+
+// Keys are sent in LOAD commands of about this many bytes, a key with larger values gets a LOAD of its own
+const LOAD_BATCH_LENGTH: usize = 1024 * 1024;
+
+fn load_command(keys: &[String], values: &[String]) -> String {
+    format!("LOAD ({}) ({})", keys.join(", "), values.join(", "))
+}
+
+// Splits the keys into LOAD commands, each has to fit into a frame the server accepts by default
+fn load_commands(map: &Multimap) -> Result<Vec<String>, String> {
+    let mut commands: Vec<String> = vec![];
+    let mut keys: Vec<String> = vec![];
+    let mut values: Vec<String> = vec![];
+    let mut length: usize = 0;
+
+    for (key, key_values) in map.iter() {
+        // Keys without values can not be expressed by LOAD
+        if key_values.is_empty() {
+            continue;
+        }
+
+        let key = quote(key);
+        let quoted: Vec<String> = key_values.iter().map(|value| quote(value)).collect();
+        let key_values = format!("({})", quoted.join(", "));
+
+        if load_command(slice::from_ref(&key), slice::from_ref(&key_values)).len() > DEFAULT_MAX_FRAME_LENGTH {
+            return Err(format!("Key {} is too long to be imported", key));
+        }
+
+        // Counted with the separators in front of the key and its values
+        let added = key.len() + key_values.len() + 4;
+        if !keys.is_empty() && length + added > LOAD_BATCH_LENGTH {
+            commands.push(load_command(&keys, &values));
+            keys.clear();
+            values.clear();
+            length = 0;
+        }
+
+        length += added;
+        keys.push(key);
+        values.push(key_values);
+    }
+
+    if !keys.is_empty() {
+        commands.push(load_command(&keys, &values));
+    }
+    Ok(commands)
+}
+
+// Loads every key of the file into the server and returns the amount of keys
+pub fn import(address: &str, args: &TransferArgs) -> Result<usize, String> {
+    let content = fs::read_to_string(&args.file).map_err(|err| format!("Could not read {}: {}", args.file, err))?;
+
+    let map: Multimap = match args.format {
+        Format::Json => serde_json::from_str(&content).map_err(|err| format!("Invalid json: {}", err))?,
+        Format::Csv => csv::decode(&content)?,
+    };
+
+    let mut client = Client::connect(address, args.token.as_deref())?;
+    for command in load_commands(&map)? {
+        client.send(&command)?;
+    }
+
+    Ok(map.values().filter(|values| !values.is_empty()).count())
+}
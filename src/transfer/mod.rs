@@ -0,0 +1,34 @@
+pub mod client;
+pub mod csv;
+pub mod export;
+pub mod import;
+
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+
+// Every key with its values, sorted so exports are stable
+pub type Multimap = BTreeMap<String, Vec<String>>;
+
+#[derive(ValueEnum, Debug, Clone)]
+#[clap(rename_all = "kebab_case")]
+pub enum Format {
+    /// A json object of key to list of values
+    Json,
+    /// One row per key, the key followed by its values
+    Csv,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TransferArgs {
+    /// File that is read from or written to
+    #[arg(short, long)]
+    pub file: String,
+
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    pub format: Format,
+
+    /// Sent with AUTH before anything else, DUMP and LOAD need a restricted permission
+    #[arg(long)]
+    pub token: Option<String>,
+}
@@ -0,0 +1,134 @@
+// Checks that AUTH swaps the permission of a connection, that --require-auth closes everyone else
+// and that reloaded permission files apply to connections that are already open
+
+mod common;
+
+use std::{
+    fs, thread,
+    time::{Duration, Instant},
+};
+
+use common::{assert_closed, send, Server};
+
+const PERMISSIONS: &str = r#"{
+    "permissions": [{ "name": "dashboard", "token": "secret" }],
+    "default": { "name": "default", "token": "" }
+}"#;
+
+#[test]
+fn auth_swaps_the_permission() {
+    let (server, _) = Server::with_permissions("auth-swap", PERMISSIONS, &["--mode", "test"]);
+    let mut stream = server.connect();
+
+    assert_eq!(send(&mut stream, "PUT (\"a\") ((\"1\"))"), "Ok");
+    assert!(send(&mut stream, "META (\"a\")").contains("WRITER:default"));
+
+    // A wrong token keeps the connection and its permission
+    assert!(send(&mut stream, "AUTH \"wrong\"").starts_with("Err: 400 PERMISSION_DENIED"));
+    assert_eq!(send(&mut stream, "AUTH \"secret\""), "Ok");
+
+    assert_eq!(send(&mut stream, "PUT (\"b\") ((\"2\"))"), "Ok");
+    assert!(send(&mut stream, "META (\"b\")").contains("WRITER:dashboard"));
+}
+
+#[test]
+fn required_auth_closes_unauthenticated_connections() {
+    let (server, _) = Server::with_permissions("auth-required", PERMISSIONS, &["--mode", "test", "--require-auth"]);
+
+    let mut stream = server.connect();
+    assert!(send(&mut stream, "GET (\"a\")").starts_with("Err: 400 PERMISSION_DENIED"));
+    assert_closed(&mut stream);
+
+    let mut stream = server.connect();
+    assert!(send(&mut stream, "AUTH \"wrong\"").starts_with("Err: 400 PERMISSION_DENIED"));
+    assert_closed(&mut stream);
+
+    let mut stream = server.connect();
+    assert_eq!(send(&mut stream, "AUTH \"secret\""), "Ok");
+    assert_eq!(send(&mut stream, "GET (\"a\")"), "[[]]");
+}
+
+#[test]
+fn reload_applies_to_open_connections() {
+    let (server, path) = Server::with_permissions("reload", PERMISSIONS, &["--mode", "test"]);
+    let mut stream = server.connect();
+    assert_eq!(send(&mut stream, "AUTH \"secret\""), "Ok");
+    assert_eq!(send(&mut stream, "PUT (\"a\") ((\"1\"))"), "Ok");
+
+    fs::write(&path, PERMISSIONS.replace("\"token\": \"secret\"", "\"token\": \"secret\", \"write\": false")).unwrap();
+    assert_eq!(send(&mut stream, "RELOAD PERMISSIONS"), "Reloaded 1 permissions");
+    assert!(send(&mut stream, "PUT (\"a\") ((\"2\"))").starts_with("Err: 400 PERMISSION_DENIED"));
+
+    // A broken file keeps the old permissions
+    fs::write(&path, "{ \"permissions\": [").unwrap();
+    assert!(send(&mut stream, "RELOAD PERMISSIONS").starts_with("Err: 401 RELOAD_FAILED: Invalid permissions"));
+    fs::write(&path, PERMISSIONS.replace("\"token\": \"secret\"", "\"token\": \"\"")).unwrap();
+    assert!(send(&mut stream, "RELOAD PERMISSIONS").starts_with("Err: 401 RELOAD_FAILED: Permission dashboard needs a token"));
+
+    let mut stream = server.connect();
+    assert_eq!(send(&mut stream, "AUTH \"secret\""), "Ok");
+}
+
+#[test]
+fn changed_file_is_picked_up() {
+    let (server, path) = Server::with_permissions("reload-poll", PERMISSIONS, &["--mode", "test"]);
+    // The server loaded the file once it accepts connections
+    let mut stream = server.connect();
+    assert_eq!(send(&mut stream, "AUTH \"secret\""), "Ok");
+    fs::write(&path, PERMISSIONS.replace("secret", "rotated")).unwrap();
+
+    let started = Instant::now();
+    loop {
+        let mut stream = server.connect();
+        if send(&mut stream, "AUTH \"rotated\"") == "Ok" {
+            assert!(send(&mut stream, "AUTH \"secret\"").starts_with("Err: 400 PERMISSION_DENIED"));
+            break;
+        }
+        assert!(started.elapsed() < Duration::from_secs(10), "Permission file was not reloaded");
+        thread::sleep(Duration::from_millis(200));
+    }
+}
+
+#[test]
+fn revoked_tokens_fall_back_to_the_default_permission() {
+    let (server, path) = Server::with_permissions("reload-downgrade", PERMISSIONS, &["--mode", "test"]);
+    let mut revoked = server.connect();
+    assert_eq!(send(&mut revoked, "AUTH \"secret\""), "Ok");
+    assert_eq!(send(&mut revoked, "PUT (\"a\") ((\"1\"))"), "Ok");
+
+    fs::write(&path, r#"{ "permissions": [], "default": { "name": "default", "token": "", "write": false } }"#).unwrap();
+    assert_eq!(send(&mut revoked, "RELOAD PERMISSIONS"), "Reloaded 0 permissions");
+
+    let response = send(&mut revoked, "PUT (\"a\") ((\"2\"))");
+    assert!(response.starts_with("Err: 400 PERMISSION_DENIED: Permission default can not write"), "{}", response);
+    assert_eq!(send(&mut revoked, "GET (\"a\")"), "[[\"1\"]]");
+}
+
+#[test]
+fn revoked_tokens_have_to_auth_again() {
+    let (server, path) = Server::with_permissions("reload-require-auth", PERMISSIONS, &["--mode", "test", "--require-auth"]);
+    let mut revoked = server.connect();
+    assert_eq!(send(&mut revoked, "AUTH \"secret\""), "Ok");
+
+    fs::write(&path, PERMISSIONS.replace("secret", "rotated")).unwrap();
+    assert_eq!(send(&mut revoked, "RELOAD PERMISSIONS"), "Reloaded 1 permissions");
+
+    assert!(send(&mut revoked, "GET (\"a\")").starts_with("Err: 400 PERMISSION_DENIED: Authenticate with AUTH first"));
+    assert_closed(&mut revoked);
+}
+
+#[test]
+fn revoked_tokens_are_disconnected() {
+    let (server, path) = Server::with_permissions("reload-revoked", PERMISSIONS, &["--mode", "test", "--disconnect-revoked"]);
+    let mut revoked = server.connect();
+    assert_eq!(send(&mut revoked, "AUTH \"secret\""), "Ok");
+    let mut default = server.connect();
+    assert_eq!(send(&mut default, "GET (\"a\")"), "[[]]");
+
+    fs::write(&path, PERMISSIONS.replace("secret", "rotated")).unwrap();
+    assert_eq!(send(&mut default, "RELOAD PERMISSIONS"), "Reloaded 1 permissions");
+
+    assert!(send(&mut revoked, "GET (\"a\")").contains("Token was revoked"));
+    assert_closed(&mut revoked);
+    assert_eq!(send(&mut default, "GET (\"a\")"), "[[]]");
+}
@@ -0,0 +1,184 @@
+// Starts the clavrs binary for a test, the server is killed when it is dropped
+// Not every test uses every helper
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub const END_OF_MESSAGE: u8 = 0x04;
+pub const FRAMED_HANDSHAKE: u8 = 0x01;
+
+pub struct Server {
+    // Shared, so the server can be killed from another thread while a test talks to it
+    pub child: Arc<Mutex<Child>>,
+    pub address: String,
+}
+
+impl Server {
+    pub fn start(args: &[&str]) -> Self {
+        // Every start gets a fresh port, so a killed server can not block the next one
+        let address = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+
+        let child = Command::new(env!("CARGO_BIN_EXE_clavrs"))
+            .arg("--address")
+            .arg(&address)
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        Server {
+            child: Arc::new(Mutex::new(child)),
+            address,
+        }
+    }
+
+    // Writes the permission file into a fresh directory, its path is returned so tests can change it
+    pub fn with_permissions(name: &str, permissions: &str, args: &[&str]) -> (Self, PathBuf) {
+        let path = test_dir(name).join("permissions.json");
+        fs::write(&path, permissions).unwrap();
+
+        let mut all = vec!["--perm-path", path.to_str().unwrap()];
+        all.extend_from_slice(args);
+        (Server::start(&all), path)
+    }
+
+    // Logs into dir and syncs every record, so nothing acknowledged is lost when the server is killed
+    pub fn with_log(dir: &Path, args: &[&str]) -> Self {
+        let log = dir.join("clavrs.log");
+        let mut all = vec!["--mode", "test", "--log-path", log.to_str().unwrap(), "--fsync", "always"];
+        all.extend_from_slice(args);
+        Server::start(&all)
+    }
+
+    // The listener is only bound once the server started, e.g. after replaying the log
+    pub fn connect(&self) -> TcpStream {
+        let started = Instant::now();
+        loop {
+            if let Ok(stream) = TcpStream::connect(&self.address) {
+                stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+                stream.set_nodelay(true).unwrap();
+                return stream;
+            }
+            assert!(started.elapsed() < Duration::from_secs(20), "Server did not start");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    pub fn connect_as(&self, token: &str) -> TcpStream {
+        let mut stream = self.connect();
+        assert_eq!(send(&mut stream, &format!("AUTH \"{}\"", token)), "Ok");
+        stream
+    }
+
+    pub fn kill_after(&self, delay: Duration) -> thread::JoinHandle<()> {
+        let child = Arc::clone(&self.child);
+        thread::spawn(move || {
+            thread::sleep(delay);
+            let mut child = child.lock().unwrap();
+            let _ = child.kill();
+            let _ = child.wait();
+        })
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let mut child = self.child.lock().unwrap();
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+// Mirrors Framing::frame of the server, the tests can not link against the binary
+pub fn terminate(command: &str) -> Vec<u8> {
+    let mut message = command.as_bytes().to_vec();
+    message.push(END_OF_MESSAGE);
+    message
+}
+
+pub fn frame(command: &str) -> Vec<u8> {
+    let mut frame = (command.len() as u32).to_be_bytes().to_vec();
+    frame.extend_from_slice(command.as_bytes());
+    frame
+}
+
+// Reads one response up to the end of message character, byte by byte so pipelined responses stay in the stream
+pub fn try_read_terminated(stream: &mut TcpStream) -> io::Result<String> {
+    let mut response = Vec::new();
+    let mut byte = [0; 1];
+    loop {
+        stream.read_exact(&mut byte)?;
+        if byte[0] == END_OF_MESSAGE {
+            return String::from_utf8(response).map_err(|err| io::Error::new(ErrorKind::InvalidData, err));
+        }
+        response.push(byte[0]);
+    }
+}
+
+pub fn read_terminated(stream: &mut TcpStream) -> String {
+    try_read_terminated(stream).unwrap()
+}
+
+// Reads one length prefixed response
+pub fn read_frame(stream: &mut TcpStream) -> String {
+    let mut header = [0; 4];
+    stream.read_exact(&mut header).unwrap();
+    let mut response = vec![0; u32::from_be_bytes(header) as usize];
+    stream.read_exact(&mut response).unwrap();
+    String::from_utf8(response).unwrap()
+}
+
+// Err means the connection broke, so the command may or may not have been applied
+pub fn try_send(stream: &mut TcpStream, command: &str) -> io::Result<String> {
+    stream.write_all(&terminate(command))?;
+    try_read_terminated(stream)
+}
+
+// Sends a terminated message and reads its response
+pub fn send(stream: &mut TcpStream, command: &str) -> String {
+    try_send(stream, command).unwrap()
+}
+
+// Connections are cleaned up after they were closed, so the response is polled
+pub fn wait_for(stream: &mut TcpStream, command: &str, expected: &str) {
+    let started = Instant::now();
+    loop {
+        let response = send(stream, command);
+        if response == expected {
+            return;
+        }
+        assert!(started.elapsed() < Duration::from_secs(10), "{} returned {}", command, response);
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+pub fn assert_closed(stream: &mut TcpStream) {
+    let mut buffer = [0; 16];
+    assert_eq!(stream.read(&mut buffer).unwrap(), 0);
+}
+
+pub fn quit(mut stream: TcpStream) {
+    stream.write_all(&terminate("QUIT")).unwrap();
+    assert_closed(&mut stream);
+}
+
+// Fresh directory for the files of one test
+pub fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("clavrs-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
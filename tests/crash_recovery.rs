@@ -0,0 +1,236 @@
+// Spawns the clavrs binary, kills it at random points and checks that every acknowledged write survives
+
+mod common;
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Write},
+    net::TcpStream,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use common::{test_dir, try_send, Server};
+
+const KEYS: usize = 16;
+const ROUNDS: usize = 6;
+
+// Small deterministic generator, the seed is printed so failing runs can be repeated with CLAVRS_TEST_SEED
+struct Lcg(u64);
+
+impl Lcg {
+    fn from_env_or_time() -> Self {
+        let seed = match env::var("CLAVRS_TEST_SEED") {
+            Ok(seed) => seed.parse().unwrap(),
+            Err(_) => SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as u64,
+        };
+        println!("CLAVRS_TEST_SEED={}", seed);
+        Lcg(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        self.0 >> 33
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+fn expect_ok(stream: &mut TcpStream, command: &str) -> io::Result<()> {
+    let response = try_send(stream, command)?;
+    assert!(!response.starts_with("Err"), "{} failed with {}", command, response);
+    Ok(())
+}
+
+type Model = HashMap<String, Vec<String>>;
+
+#[derive(Debug, Clone)]
+enum Change {
+    Put(String, String),
+    Replace(String, String),
+    Delete(String),
+}
+
+impl Change {
+    fn apply(&self, model: &mut Model) {
+        match self {
+            Change::Put(key, value) => model.entry(key.clone()).or_default().push(value.clone()),
+            Change::Replace(key, value) => {
+                model.insert(key.clone(), vec![value.clone()]);
+            }
+            Change::Delete(key) => {
+                model.remove(key);
+            }
+        }
+    }
+
+    fn command(&self) -> String {
+        match self {
+            Change::Put(key, value) => format!("PUT (\"{}\") ((\"{}\"))", key, value),
+            Change::Replace(key, value) => format!("REPLACE \"{}\" (\"{}\")", key, value),
+            Change::Delete(key) => format!("DELETE (\"{}\")", key),
+        }
+    }
+}
+
+fn random_change(rng: &mut Lcg, counter: &mut usize) -> Change {
+    let key = format!("k{}", rng.below(KEYS as u64));
+    *counter += 1;
+    let value = format!("v{}", counter);
+    match rng.below(4) {
+        0 => Change::Delete(key),
+        1 => Change::Replace(key, value),
+        _ => Change::Put(key, value),
+    }
+}
+
+// Parses [["a", "b"], []] into the values of each key
+fn parse_get(response: &str) -> Vec<Vec<String>> {
+    let inner = response.trim().strip_prefix('[').unwrap().strip_suffix(']').unwrap();
+    inner
+        .split(']')
+        .filter_map(|part| part.split_once('['))
+        .map(|(_, values)| {
+            values
+                .split(',')
+                .map(|value| value.trim().trim_matches('"').to_string())
+                .filter(|value| !value.is_empty())
+                .collect()
+        })
+        .collect()
+}
+
+fn read_state(stream: &mut TcpStream) -> Model {
+    let keys: Vec<String> = (0..KEYS).map(|i| format!("\"k{}\"", i)).collect();
+    let response = try_send(stream, &format!("GET ({})", keys.join(", "))).unwrap();
+
+    let mut state = Model::new();
+    for (i, mut values) in parse_get(&response).into_iter().enumerate() {
+        if !values.is_empty() {
+            values.sort();
+            state.insert(format!("k{}", i), values);
+        }
+    }
+    state
+}
+
+fn normalized(model: &Model) -> Model {
+    model
+        .iter()
+        .filter(|(_, values)| !values.is_empty())
+        .map(|(key, values)| {
+            let mut values = values.clone();
+            values.sort();
+            (key.clone(), values)
+        })
+        .collect()
+}
+
+#[test]
+fn acknowledged_writes_survive_random_kills() {
+    let dir = test_dir("crash");
+    let snapshots = dir.join("snapshots");
+    let args = ["--snapshot-dir", snapshots.to_str().unwrap()];
+    let mut rng = Lcg::from_env_or_time();
+    let mut model = Model::new();
+    let mut counter: usize = 0;
+
+    for round in 0..ROUNDS {
+        let server = Server::with_log(&dir, &args);
+        let mut stream = server.connect();
+
+        let recovered = read_state(&mut stream);
+        // Changes that were sent but not acknowledged before the kill may or may not be applied
+        if recovered != normalized(&model) {
+            panic!("Round {}: recovered {:?} but acknowledged {:?}", round, recovered, normalized(&model));
+        }
+
+        let killer = server.kill_after(Duration::from_millis(50 + rng.below(400)));
+        let mut pending: Vec<Change> = vec![];
+
+        loop {
+            let result = match rng.below(10) {
+                0 => {
+                    // A transaction is a single entry in the log, so it is applied completely or not at all
+                    pending = (0..1 + rng.below(4)).map(|_| random_change(&mut rng, &mut counter)).collect();
+                    let mut commands = vec!["SEQUENCE".to_string()];
+                    commands.extend(pending.iter().map(|change| change.command()));
+                    commands.push("EXECUTE".to_string());
+                    commands.iter().try_for_each(|command| expect_ok(&mut stream, command))
+                }
+                1 => try_send(&mut stream, "REWRITE").map(|_| ()),
+                2 => try_send(&mut stream, "SNAPSHOT").map(|_| ()),
+                _ => {
+                    pending = vec![random_change(&mut rng, &mut counter)];
+                    expect_ok(&mut stream, &pending[0].command())
+                }
+            };
+
+            match result {
+                Ok(()) => {
+                    for change in pending.drain(..) {
+                        change.apply(&mut model);
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        killer.join().unwrap();
+        drop(server);
+
+        if pending.is_empty() {
+            continue;
+        }
+
+        // Find out if the unacknowledged change made it, everything else has to match exactly
+        let server = Server::with_log(&dir, &args);
+        let recovered = read_state(&mut server.connect());
+        let mut applied = model.clone();
+        for change in pending.iter() {
+            change.apply(&mut applied);
+        }
+
+        if recovered == normalized(&applied) {
+            model = applied;
+        } else if recovered != normalized(&model) {
+            panic!("Round {}: recovered {:?} but acknowledged {:?}", round, recovered, normalized(&model));
+        }
+    }
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn torn_log_entry_is_cut_off() {
+    let dir = test_dir("torn");
+    let snapshots = dir.join("snapshots");
+    let args = ["--snapshot-dir", snapshots.to_str().unwrap()];
+
+    {
+        let server = Server::with_log(&dir, &args);
+        let mut stream = server.connect();
+        expect_ok(&mut stream, "PUT (\"a\") ((\"1\"))").unwrap();
+        expect_ok(&mut stream, "PUT (\"b\") ((\"2\"))").unwrap();
+    }
+
+    // A crash in the middle of a write leaves half a line behind
+    let mut log = fs::OpenOptions::new().append(true).open(dir.join("clavrs.log")).unwrap();
+    log.write_all(b"{\"seq\":99,\"origin\":{\"na").unwrap();
+    drop(log);
+
+    {
+        let server = Server::with_log(&dir, &args);
+        let mut stream = server.connect();
+        expect_ok(&mut stream, "PUT (\"c\") ((\"3\"))").unwrap();
+    }
+
+    let server = Server::with_log(&dir, &args);
+    let mut stream = server.connect();
+    let response = try_send(&mut stream, "GET (\"a\", \"b\", \"c\")").unwrap();
+    assert_eq!(parse_get(&response), vec![vec!["1"], vec!["2"], vec!["3"]]);
+
+    drop(server);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,158 @@
+// Sends messages in both framings with awkward chunking and checks that they arrive intact
+
+mod common;
+
+use std::{
+    fs,
+    io::{Read, Write},
+    net::TcpStream,
+    path::Path,
+    process::Command,
+};
+
+use common::{frame, read_frame, read_terminated, send, terminate, test_dir, Server, END_OF_MESSAGE, FRAMED_HANDSHAKE};
+use serde_json::Value;
+
+// Writes the bytes in small pieces, so multibyte characters are split between reads
+fn write_in_pieces(stream: &mut TcpStream, bytes: &[u8], piece: usize) {
+    for chunk in bytes.chunks(piece) {
+        stream.write_all(chunk).unwrap();
+        stream.flush().unwrap();
+    }
+}
+
+fn read_json(stream: &mut TcpStream) -> Value {
+    serde_json::from_str(&read_frame(stream)).unwrap()
+}
+
+#[test]
+fn framed_values_keep_any_character() {
+    let server = Server::start(&["--mode", "test"]);
+    let mut stream = server.connect();
+
+    stream.write_all(&[FRAMED_HANDSHAKE]).unwrap();
+    write_in_pieces(&mut stream, &frame("FORMAT \"json\""), 3);
+    assert_eq!(read_json(&mut stream)["type"], "ok");
+
+    // Several megabytes of multibyte characters, the terminator and a line break
+    let value: String = "ü€\u{4}\n".repeat(500_000);
+    let put = format!("PUT (\"big\") ((\"{}\"))", value);
+    assert!(put.len() > 3_000_000);
+    write_in_pieces(&mut stream, &frame(&put), 1001);
+    assert_eq!(read_json(&mut stream)["type"], "ok");
+
+    let has = format!("HAS (\"big\") \"{}\"", value);
+    write_in_pieces(&mut stream, &frame(&has), 65536);
+    assert_eq!(read_json(&mut stream)["value"], serde_json::json!([true]));
+
+    write_in_pieces(&mut stream, &frame("GET (\"big\")"), 1);
+    let response = read_json(&mut stream);
+    assert_eq!(response["value"][0][0].as_str().unwrap(), value);
+}
+
+#[test]
+fn oversized_frame_closes_connection() {
+    let server = Server::start(&["--mode", "test", "--max-frame-length", "1024"]);
+    let mut stream = server.connect();
+
+    stream.write_all(&[FRAMED_HANDSHAKE]).unwrap();
+    stream.write_all(&2048u32.to_be_bytes()).unwrap();
+    assert!(read_frame(&mut stream).starts_with("Err: 701 MESSAGE_EXCEEDS_MAX_LENGTH"));
+
+    let mut buffer = [0; 16];
+    assert_eq!(stream.read(&mut buffer).unwrap(), 0);
+}
+
+#[test]
+fn invalid_utf8_only_rejects_the_message() {
+    let server = Server::start(&["--mode", "test"]);
+
+    let mut stream = server.connect();
+    stream.write_all(&[FRAMED_HANDSHAKE]).unwrap();
+    let mut invalid = frame("GET (\"a\")");
+    invalid.extend(frame("PUT (\"a\") ((\"x\"))"));
+    // Inside the first frame, behind its 4 byte header
+    invalid[4 + 5] = 0xff;
+    stream.write_all(&invalid).unwrap();
+    assert_eq!(read_frame(&mut stream), "Err: 703 INVALID_UTF8: Message is not valid Utf8");
+    assert_eq!(read_frame(&mut stream), "Ok");
+
+    let mut stream = server.connect();
+    stream.write_all(&[b'G', 0xc3, END_OF_MESSAGE]).unwrap();
+    assert_eq!(read_terminated(&mut stream), "Err: 703 INVALID_UTF8: Message is not valid Utf8");
+    assert_eq!(send(&mut stream, "GET (\"a\")"), "[[\"x\"]]");
+}
+
+#[test]
+fn terminated_message_split_inside_character() {
+    let server = Server::start(&["--mode", "test"]);
+    let mut stream = server.connect();
+
+    write_in_pieces(&mut stream, &terminate("PUT (\"key\") ((\"ü\"))"), 1);
+    assert_eq!(read_terminated(&mut stream), "Ok");
+
+    assert_eq!(send(&mut stream, "GET (\"key\")"), "[[\"ü\"]]");
+}
+
+#[test]
+fn pipelined_messages_are_answered_in_order() {
+    let server = Server::start(&["--mode", "test"]);
+    let mut stream = server.connect();
+
+    let mut messages = Vec::new();
+    for command in ["PUT (\"a\") ((\"1\"))", "PUT (\"b\") ((\"2\"))", "NOPE", "GET (\"a\", \"b\")"] {
+        messages.extend(terminate(command));
+    }
+    stream.write_all(&messages).unwrap();
+
+    assert_eq!(read_terminated(&mut stream), "Ok");
+    assert_eq!(read_terminated(&mut stream), "Ok");
+    assert!(read_terminated(&mut stream).starts_with("Err: 200 INVALID_TOKEN 1"));
+    assert_eq!(read_terminated(&mut stream), "[[\"1\"], [\"2\"]]");
+}
+
+#[test]
+fn pipelined_frames_are_answered_in_order() {
+    let server = Server::start(&["--mode", "test"]);
+    let mut stream = server.connect();
+
+    let mut frames = vec![FRAMED_HANDSHAKE];
+    for command in ["FORMAT \"json\"", "PUT (\"a\") ((\"\u{4}\"))", "GET (\"a\")", "EXISTS (\"a\", \"b\")"] {
+        frames.extend(frame(command));
+    }
+    // Split in the middle of a frame header
+    write_in_pieces(&mut stream, &frames, 7);
+
+    assert_eq!(read_json(&mut stream)["type"], "ok");
+    assert_eq!(read_json(&mut stream)["type"], "ok");
+    assert_eq!(read_json(&mut stream)["value"], serde_json::json!([["\u{4}"]]));
+    assert_eq!(read_json(&mut stream)["value"], serde_json::json!([true, false]));
+}
+
+#[test]
+fn import_and_export_keep_any_character() {
+    let server = Server::start(&["--mode", "test"]);
+    // The server is up once it accepts a connection
+    server.connect();
+    let dir = test_dir("transfer-framing");
+    let (import, export) = (dir.join("import.json"), dir.join("export.json"));
+
+    // A key longer than a terminated message and values with the terminator
+    let mut map = serde_json::Map::new();
+    map.insert("k".repeat(4096), serde_json::json!(["\u{4}", "a\"b\\c"]));
+    map.insert("small".to_string(), serde_json::json!(["ü\n"]));
+    fs::write(&import, Value::Object(map.clone()).to_string()).unwrap();
+
+    let transfer = |command: &str, file: &Path| {
+        let output = Command::new(env!("CARGO_BIN_EXE_clavrs"))
+            .args(["--address", &server.address, command, "--file", file.to_str().unwrap()])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    };
+    transfer("import", &import);
+    transfer("export", &export);
+
+    let exported: Value = serde_json::from_str(&fs::read_to_string(&export).unwrap()).unwrap();
+    assert_eq!(exported, Value::Object(map));
+}
@@ -0,0 +1,161 @@
+// Checks when keys of the different lifetimes are removed
+
+mod common;
+
+use std::{
+    fs, thread,
+    time::{Duration, Instant},
+};
+
+use common::{send, test_dir, wait_for, Server};
+
+#[test]
+fn connection_keys_are_removed_when_the_socket_is_dropped() {
+    let server = Server::start(&["--mode", "test"]);
+
+    let mut owner = server.connect();
+    assert_eq!(send(&mut owner, "PUT ['c] (\"a\") ((\"1\"))"), "Ok");
+    assert_eq!(send(&mut owner, "PUT (\"b\") ((\"2\"))"), "Ok");
+
+    let mut other = server.connect();
+    assert_eq!(send(&mut other, "EXISTS (\"a\", \"b\")"), "[true, true]");
+
+    // Dropped without QUIT
+    drop(owner);
+    wait_for(&mut other, "EXISTS (\"a\", \"b\")", "[false, true]");
+}
+
+#[test]
+fn date_keys_are_hidden_and_reaped_after_their_date() {
+    let dir = test_dir("date-lifetime");
+    let server = Server::with_log(&dir, &[]);
+    let mut stream = server.connect();
+
+    assert_eq!(send(&mut stream, "PUT ['d \"1s\"] (\"a\") ((\"1\"))"), "Ok");
+    assert_eq!(send(&mut stream, "GET (\"a\")"), "[[\"1\"]]");
+
+    thread::sleep(Duration::from_millis(1100));
+    assert_eq!(send(&mut stream, "GET (\"a\")"), "[[]]");
+    assert_eq!(send(&mut stream, "EXISTS (\"a\")"), "[false]");
+    assert_eq!(send(&mut stream, "HAS (\"a\") \"1\""), "[false]");
+
+    // The reaper removes the key and logs it
+    let started = Instant::now();
+    while !fs::read_to_string(dir.join("clavrs.log")).unwrap().contains("\"Expire\"") {
+        assert!(started.elapsed() < Duration::from_secs(10), "Key was not reaped");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn invalid_dates_are_rejected() {
+    let server = Server::start(&["--mode", "test"]);
+    let mut stream = server.connect();
+
+    let past = send(&mut stream, "PUT ['d \"2001-01-01\"] (\"a\") ((\"1\"))");
+    assert!(past.contains("Date Lifetime lies in the past"), "{}", past);
+    let unit = send(&mut stream, "PUT ['d \"5x\"] (\"a\") ((\"1\"))");
+    assert!(unit.contains("units are s, m, h and d"), "{}", unit);
+    assert_eq!(send(&mut stream, "EXISTS (\"a\")"), "[false]");
+}
+
+#[test]
+fn references_have_to_be_created_once() {
+    let server = Server::start(&["--mode", "test"]);
+    let mut stream = server.connect();
+
+    for command in ["WITH [&'s A]", "PUT [&'s A] (\"a\") ((\"1\"))", "DROP [&'s A]", "EXPIRE [&'s A]"] {
+        assert!(send(&mut stream, command).ends_with("Lifetime Reference A was not created"), "{}", command);
+    }
+
+    assert_eq!(send(&mut stream, "CREATE [&'s A]"), "Ok");
+    assert!(send(&mut stream, "CREATE [&'s A]").ends_with("Lifetime Reference A already exists"));
+
+    assert_eq!(send(&mut stream, "WITH [&'s A]"), "Ok");
+    assert_eq!(send(&mut stream, "PUT (\"a\", \"b\") ((\"1\"), (\"2\"))"), "Ok");
+    assert_eq!(send(&mut stream, "DROP [&'s A]"), "Ok");
+    assert_eq!(send(&mut stream, "EXISTS (\"a\", \"b\")"), "[false, false]");
+
+    // Dropping keeps the reference, expiring ends it
+    assert_eq!(send(&mut stream, "PUT (\"a\") ((\"1\"))"), "Ok");
+    assert_eq!(send(&mut stream, "EXPIRE [&'s A]"), "Ok");
+    assert_eq!(send(&mut stream, "EXISTS (\"a\")"), "[false]");
+    assert!(send(&mut stream, "PUT [&'s A] (\"a\") ((\"1\"))").ends_with("Lifetime Reference A was not created"));
+}
+
+#[test]
+fn connection_references_expire_with_their_connection() {
+    let server = Server::start(&["--mode", "test"]);
+
+    let mut owner = server.connect();
+    assert_eq!(send(&mut owner, "CREATE [&'c A]"), "Ok");
+    assert_eq!(send(&mut owner, "PUT [&'c A] (\"a\") ((\"1\"))"), "Ok");
+
+    let mut other = server.connect();
+    assert_eq!(send(&mut other, "EXISTS (\"a\")"), "[true]");
+
+    drop(owner);
+    wait_for(&mut other, "EXISTS (\"a\")", "[false]");
+
+    // The name is free again
+    assert_eq!(send(&mut other, "CREATE [&'s A]"), "Ok");
+}
+
+#[test]
+fn move_is_all_or_nothing() {
+    let server = Server::start(&["--mode", "test"]);
+    let mut stream = server.connect();
+
+    assert_eq!(send(&mut stream, "PUT (\"a\", \"b\") ((\"1\"), (\"2\"))"), "Ok");
+    assert!(send(&mut stream, "MOVE (\"a\", \"missing\", \"b\") ['c]").ends_with("Key missing does not exist"));
+    assert_eq!(send(&mut stream, "META (\"a\", \"b\")").matches("LT:'s").count(), 2);
+
+    assert_eq!(send(&mut stream, "MOVE (\"a\", \"b\") ['c]"), "Ok");
+    assert_eq!(send(&mut stream, "META (\"a\", \"b\")").matches("LT:'c").count(), 2);
+
+    // The values are untouched and go with the connection now
+    let mut other = server.connect();
+    assert_eq!(send(&mut other, "GET (\"a\", \"b\")"), "[[\"1\"], [\"2\"]]");
+    drop(stream);
+    wait_for(&mut other, "EXISTS (\"a\", \"b\")", "[false, false]");
+}
+
+#[test]
+fn linked_keys_live_until_the_last_subscriber_leaves() {
+    let server = Server::start(&["--mode", "test"]);
+    let mut observer = server.connect();
+
+    let mut first = server.connect();
+    assert_eq!(send(&mut first, "PUT ['l \"room\"] (\"a\") ((\"1\"))"), "Ok");
+    let mut second = server.connect();
+    assert_eq!(send(&mut second, "WITH ['l \"room\"]"), "Ok");
+
+    drop(first);
+    // Give the server time to clean up the first connection, the key has to stay
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(send(&mut observer, "EXISTS (\"a\")"), "[true]");
+
+    drop(second);
+    wait_for(&mut observer, "EXISTS (\"a\")", "[false]");
+}
+
+#[test]
+fn taken_references_are_exclusive() {
+    let server = Server::start(&["--mode", "test"]);
+
+    let mut taker = server.connect();
+    assert_eq!(send(&mut taker, "CREATE [&'s A]"), "Ok");
+    assert_eq!(send(&mut taker, "TAKE [&'s A]"), "Ok");
+    assert_eq!(send(&mut taker, "PUT [&'s A] (\"a\") ((\"1\"))"), "Ok");
+
+    let mut other = server.connect();
+    for command in ["WITH [&'s A]", "PUT [&'s A] (\"b\") ((\"2\"))", "DROP [&'s A]", "EXPIRE [&'s A]", "TAKE [&'s A]"] {
+        assert!(send(&mut other, command).ends_with("was taken by another Connection"), "{}", command);
+    }
+    assert_eq!(send(&mut other, "EXISTS (\"a\", \"b\")"), "[true, false]");
+
+    // The reference is given back when the taker closes, its keys stay
+    drop(taker);
+    wait_for(&mut other, "TAKE [&'s A]", "Ok");
+    assert_eq!(send(&mut other, "EXISTS (\"a\")"), "[true]");
+}
@@ -0,0 +1,219 @@
+// Checks that the capabilities of the permission file are enforced
+
+mod common;
+
+use std::process::Command;
+
+use common::{assert_closed, send, test_dir, Server};
+
+// The servers run outside of test mode, so restricted commands are not allowed for everyone
+const PERMISSIONS: &str = r#"{
+    "permissions": [
+        { "name": "dashboard", "token": "read", "write": false, "transaction": false },
+        { "name": "writer", "token": "write", "transaction": false },
+        { "name": "admin", "token": "admin", "restricted": true },
+        { "name": "tenant", "token": "tenant", "allow_keys": ["tenant_a:*"], "deny_keys": ["tenant_a:secret?"] },
+        { "name": "local", "token": "local", "allow_ips": ["127.0.0.0/8", "::1"] },
+        { "name": "office", "token": "office", "allow_ips": ["10.0.0.0/8"] },
+        { "name": "blocked", "token": "blocked", "deny_ips": ["127.0.0.1"] }
+    ],
+    "default": { "name": "default", "token": "" }
+}"#;
+
+fn assert_denied(response: String, message: &str) {
+    assert!(response.starts_with("Err: 400 PERMISSION_DENIED"), "{}", response);
+    assert!(response.contains(message), "{}", response);
+}
+
+#[test]
+fn read_only_permission_can_not_change_keys() {
+    let (server, _) = Server::with_permissions("capabilities-read", PERMISSIONS, &[]);
+    let mut stream = server.connect_as("read");
+
+    assert_eq!(send(&mut stream, "GET (\"a\")"), "[[]]");
+    assert_eq!(send(&mut stream, "EXISTS (\"a\")"), "[false]");
+    assert_denied(send(&mut stream, "PUT (\"a\") ((\"1\"))"), "dashboard can not write");
+    assert_denied(send(&mut stream, "POP \"a\""), "dashboard can not write");
+    assert_denied(send(&mut stream, "SEQUENCE"), "dashboard can not use transactions");
+}
+
+#[test]
+fn transactions_need_their_capability() {
+    let (server, _) = Server::with_permissions("capabilities-transaction", PERMISSIONS, &[]);
+    let mut stream = server.connect_as("write");
+
+    assert_eq!(send(&mut stream, "PUT (\"a\") ((\"1\"))"), "Ok");
+    assert_eq!(send(&mut stream, "POP \"a\""), "[\"1\"]");
+    assert_denied(send(&mut stream, "SEQUENCE"), "writer can not use transactions");
+    assert_denied(send(&mut stream, "DUMP"), "writer can not use restricted Commands");
+}
+
+#[test]
+fn restricted_permission_can_use_admin_commands() {
+    let (server, _) = Server::with_permissions("capabilities-restricted", PERMISSIONS, &[]);
+
+    let mut stream = server.connect();
+    assert_denied(send(&mut stream, "DUMP"), "default can not use restricted Commands");
+
+    let mut stream = server.connect_as("admin");
+    assert_eq!(send(&mut stream, "PUT (\"a\") ((\"1\"))"), "Ok");
+    assert_eq!(send(&mut stream, "DUMP"), "{\"a\":[\"1\"]}");
+}
+
+#[test]
+fn key_patterns_limit_the_keys() {
+    let (server, _) = Server::with_permissions("key-patterns", PERMISSIONS, &[]);
+    let mut stream = server.connect_as("tenant");
+
+    assert_eq!(send(&mut stream, "PUT (\"tenant_a:1\") ((\"1\"))"), "Ok");
+    assert_denied(send(&mut stream, "PUT (\"tenant_b:1\") ((\"1\"))"), "can not access key \"tenant_b:1\"");
+    assert_denied(send(&mut stream, "GET (\"tenant_a:1\", \"tenant_b:1\")"), "can not access key \"tenant_b:1\"");
+    assert_denied(send(&mut stream, "POP \"tenant_a:secret1\""), "can not access key \"tenant_a:secret1\"");
+    assert_eq!(send(&mut stream, "GET (\"tenant_a:1\", \"tenant_a:secret\")"), "[[\"1\"], []]");
+
+    // Denied operations are not queued
+    assert_eq!(send(&mut stream, "SEQUENCE"), "Ok");
+    assert_eq!(send(&mut stream, "PUT (\"tenant_a:2\") ((\"2\"))"), "+Queue");
+    assert_denied(send(&mut stream, "DELETE (\"tenant_b:1\")"), "can not access key \"tenant_b:1\"");
+    assert!(!send(&mut stream, "EXECUTE").starts_with("Err"));
+    assert_eq!(send(&mut stream, "EXISTS (\"tenant_a:2\")"), "[true]");
+}
+
+#[test]
+fn queued_operations_are_checked_again_on_execute() {
+    let (server, _) = Server::with_permissions("key-patterns-execute", PERMISSIONS, &[]);
+    let mut stream = server.connect();
+
+    assert_eq!(send(&mut stream, "SEQUENCE"), "Ok");
+    assert_eq!(send(&mut stream, "PUT (\"tenant_a:1\") ((\"1\"))"), "+Queue");
+    assert_eq!(send(&mut stream, "PUT (\"tenant_b:1\") ((\"1\"))"), "+Queue");
+    assert_eq!(send(&mut stream, "AUTH \"tenant\""), "Ok");
+    assert_denied(send(&mut stream, "EXECUTE"), "can not access key \"tenant_b:1\"");
+
+    // Nothing of the transaction was applied
+    let mut stream = server.connect();
+    assert_eq!(send(&mut stream, "EXISTS (\"tenant_a:1\", \"tenant_b:1\")"), "[false, false]");
+}
+
+#[test]
+fn lifetime_instructions_check_every_key() {
+    let (server, _) = Server::with_permissions("key-patterns-lifetimes", PERMISSIONS, &[]);
+    let mut other = server.connect();
+    assert_eq!(send(&mut other, "CREATE [&'s shared]"), "Ok");
+    assert_eq!(send(&mut other, "PUT [&'s shared] (\"tenant_a:1\", \"tenant_b:1\") ((\"1\"), (\"1\"))"), "Ok");
+
+    let mut stream = server.connect_as("tenant");
+    assert_denied(send(&mut stream, "DROP [&'s shared]"), "can not access key \"tenant_b:1\"");
+    assert_denied(send(&mut stream, "TAKE [&'s shared]"), "can not access key \"tenant_b:1\"");
+    assert_eq!(send(&mut other, "EXISTS (\"tenant_a:1\", \"tenant_b:1\")"), "[true, true]");
+
+    // Lifetimes with only allowed keys can be dropped
+    assert_eq!(send(&mut stream, "PUT ['u] (\"tenant_a:2\") ((\"2\"))"), "Ok");
+    assert_eq!(send(&mut stream, "DROP ['u]"), "Ok");
+    assert_eq!(send(&mut stream, "EXISTS (\"tenant_a:2\")"), "[false]");
+}
+
+#[test]
+fn references_belong_to_their_creator() {
+    let (server, _) = Server::with_permissions("reference-creator", PERMISSIONS, &[]);
+    let mut creator = server.connect_as("write");
+    assert_eq!(send(&mut creator, "CREATE [&'s shared]"), "Ok");
+    assert_eq!(send(&mut creator, "PUT [&'s shared] (\"a\") ((\"1\"))"), "Ok");
+
+    // Other users may use the reference, but not take or expire it
+    let mut other = server.connect();
+    assert_eq!(send(&mut other, "PUT [&'s shared] (\"b\") ((\"2\"))"), "Ok");
+    assert_eq!(
+        send(&mut other, "TAKE [&'s shared]"),
+        "Err: 400 PERMISSION_DENIED: Lifetime Reference shared was created by another User"
+    );
+    assert!(send(&mut other, "EXPIRE [&'s shared]").contains("was created by another User"));
+    assert_eq!(send(&mut other, "EXISTS (\"a\", \"b\")"), "[true, true]");
+
+    let mut admin = server.connect_as("admin");
+    assert_eq!(send(&mut admin, "EXPIRE [&'s shared]"), "Ok");
+    assert_eq!(send(&mut creator, "EXISTS (\"a\", \"b\")"), "[false, false]");
+}
+
+#[test]
+fn owned_keys_can_only_be_changed_by_their_owner() {
+    let (server, _) = Server::with_permissions("key-owners", PERMISSIONS, &[]);
+    let mut owner = server.connect_as("write");
+    assert_eq!(send(&mut owner, "PUT ['u] (\"user\") ((\"1\"))"), "Ok");
+    assert_eq!(send(&mut owner, "PUT ['c] (\"connection\") ((\"1\"))"), "Ok");
+
+    let mut other = server.connect();
+    for command in [
+        "PUT (\"free\", \"user\") ((\"2\"), (\"2\"))",
+        "REPLACE \"user\" (\"2\")",
+        "DELETE (\"user\")",
+        "CLEAR (\"user\")",
+        "RETRACT (\"user\") (\"1\")",
+        "POP \"user\"",
+    ] {
+        assert!(send(&mut other, command).ends_with("Key user is owned by another User"), "{}", command);
+    }
+    assert!(send(&mut other, "DELETE (\"connection\")").ends_with("Key connection is owned by another Connection"));
+
+    // Nothing was changed, not even the keys that were not owned
+    assert_eq!(send(&mut other, "GET (\"free\", \"user\", \"connection\")"), "[[], [\"1\"], [\"1\"]]");
+
+    // Restricted rights allow changing keys of other users, but not of other connections
+    let mut admin = server.connect_as("admin");
+    let load = send(&mut admin, "LOAD (\"free\", \"connection\") ((\"2\"), (\"2\"))");
+    assert_eq!(load, "Err: 400 PERMISSION_DENIED: Key connection is owned by another Connection");
+    assert_eq!(send(&mut admin, "EXISTS (\"free\")"), "[false]");
+    assert_eq!(send(&mut admin, "DELETE (\"user\")"), "Ok");
+    assert!(send(&mut admin, "POP \"connection\"").ends_with("Key connection is owned by another Connection"));
+    assert_eq!(send(&mut owner, "POP \"connection\""), "[\"1\"]");
+}
+
+#[test]
+fn export_and_import_authenticate_with_the_token() {
+    let (server, _) = Server::with_permissions("transfer-token", PERMISSIONS, &[]);
+    let file = test_dir("transfer-token-file").join("dump.json");
+    let mut stream = server.connect_as("admin");
+    assert_eq!(send(&mut stream, "PUT (\"a\") ((\"1\"))"), "Ok");
+
+    let transfer = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_clavrs"))
+            .args(["--address", &server.address])
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = transfer(&["export", "--file", file.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("PERMISSION_DENIED"));
+
+    assert!(transfer(&["export", "--file", file.to_str().unwrap(), "--token", "admin"]).status.success());
+    assert_eq!(send(&mut stream, "DELETE (\"a\")"), "Ok");
+    assert!(transfer(&["import", "--file", file.to_str().unwrap(), "--token", "admin"]).status.success());
+    assert_eq!(send(&mut stream, "GET (\"a\")"), "[[\"1\"]]");
+
+    let output = transfer(&["import", "--file", file.to_str().unwrap(), "--token", "unknown"]);
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Could not authenticate"));
+}
+
+#[test]
+fn tokens_are_only_valid_from_their_networks() {
+    let (server, _) = Server::with_permissions("ip-tokens", PERMISSIONS, &[]);
+
+    let mut stream = server.connect_as("local");
+    assert_eq!(send(&mut stream, "GET (\"a\")"), "[[]]");
+
+    let mut stream = server.connect();
+    assert_denied(send(&mut stream, "AUTH \"office\""), "Invalid token");
+    assert_denied(send(&mut stream, "AUTH \"blocked\""), "Invalid token");
+}
+
+#[test]
+fn server_allowlist_closes_other_networks() {
+    let (server, _) = Server::with_permissions("ip-allowlist", PERMISSIONS, &["--allow-ip", "10.0.0.0/8"]);
+    assert_closed(&mut server.connect());
+
+    let (server, _) = Server::with_permissions("ip-allowlist-local", PERMISSIONS, &["--allow-ip", "10.0.0.0/8", "--allow-ip", "127.0.0.1"]);
+    let mut stream = server.connect();
+    assert_eq!(send(&mut stream, "GET (\"a\")"), "[[]]");
+}
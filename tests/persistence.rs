@@ -0,0 +1,208 @@
+// Checks what ends up in the write log
+
+mod common;
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    net::TcpStream,
+    path::Path,
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{quit, send, test_dir, Server};
+
+fn wait_for_rewrite(stream: &mut TcpStream) {
+    let started = Instant::now();
+    loop {
+        let status = send(stream, "REWRITE STATUS");
+        if status.contains("Finished") {
+            return;
+        }
+        assert!(!status.contains("Failed"), "{}", status);
+        assert!(started.elapsed() < Duration::from_secs(20), "Rewrite did not finish: {}", status);
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+// Compares the values of every key in the model and of every key that was written, in batches that fit into a message
+fn assert_state(stream: &mut TcpStream, keys: &[String], model: &BTreeMap<String, Vec<String>>) {
+    for batch in keys.chunks(50) {
+        let quoted: Vec<String> = batch.iter().map(|key| format!("\"{}\"", key)).collect();
+        let expected: Vec<String> = batch
+            .iter()
+            .map(|key| match model.get(key) {
+                Some(values) => format!("[{}]", values.iter().map(|value| format!("\"{}\"", value)).collect::<Vec<_>>().join(", ")),
+                None => "[]".to_string(),
+            })
+            .collect();
+        assert_eq!(send(stream, &format!("GET ({})", quoted.join(", "))), format!("[{}]", expected.join(", ")));
+    }
+}
+
+fn read_log(dir: &Path) -> Vec<String> {
+    fs::read_to_string(dir.join("clavrs.log")).unwrap_or_default().lines().map(String::from).collect()
+}
+
+// Connections are cleaned up after they were closed, so the log is polled
+fn wait_for_log(dir: &Path, entries: usize) -> Vec<String> {
+    let started = Instant::now();
+    while read_log(dir).len() < entries && started.elapsed() < Duration::from_secs(10) {
+        thread::sleep(Duration::from_millis(20));
+    }
+    thread::sleep(Duration::from_millis(200));
+    read_log(dir)
+}
+
+#[test]
+fn closed_connections_only_log_what_they_expired() {
+    let dir = test_dir("log-connection-expire");
+    let server = Server::with_log(&dir, &[]);
+
+    for _ in 0..5 {
+        let mut stream = server.connect();
+        assert_eq!(send(&mut stream, "GET (\"a\")"), "[[]]");
+        quit(stream);
+    }
+
+    let mut stream = server.connect();
+    assert_eq!(send(&mut stream, "PUT ['c] (\"a\") ((\"1\"))"), "Ok");
+    quit(stream);
+
+    let log = wait_for_log(&dir, 2);
+    assert_eq!(log.len(), 2, "{:?}", log);
+    assert!(log[0].contains("\"Write\""), "{}", log[0]);
+    assert!(log[1].contains("\"Expire\""), "{}", log[1]);
+}
+
+#[test]
+fn corrupt_snapshot_falls_back_to_an_older_one() {
+    let dir = test_dir("snapshot-fallback");
+    let snapshots = dir.join("snapshots");
+    let args = ["--snapshot-dir", snapshots.to_str().unwrap()];
+
+    {
+        let server = Server::with_log(&dir, &args);
+        let mut stream = server.connect();
+        assert_eq!(send(&mut stream, "PUT (\"a\") ((\"1\"))"), "Ok");
+        assert_eq!(send(&mut stream, "SNAPSHOT"), "Ok");
+        assert_eq!(send(&mut stream, "PUT (\"b\") ((\"2\"))"), "Ok");
+        assert_eq!(send(&mut stream, "SNAPSHOT"), "Ok");
+    }
+
+    let mut paths: Vec<_> = fs::read_dir(&snapshots).unwrap().map(|entry| entry.unwrap().path()).collect();
+    paths.sort();
+    assert_eq!(paths.len(), 2);
+    fs::write(paths.last().unwrap(), "{\"seq\": ").unwrap();
+
+    let server = Server::with_log(&dir, &args);
+    let mut stream = server.connect();
+    assert_eq!(send(&mut stream, "GET (\"a\", \"b\")"), "[[\"1\"], [\"2\"]]");
+}
+
+#[test]
+fn log_without_sequence_numbers_is_not_replayed_over_a_snapshot() {
+    let dir = test_dir("log-upgrade");
+    let snapshots = dir.join("snapshots");
+    let args = ["--snapshot-dir", snapshots.to_str().unwrap()];
+
+    {
+        let server = Server::with_log(&dir, &[]);
+        let mut stream = server.connect();
+        assert_eq!(send(&mut stream, "PUT (\"a\") ((\"1\"))"), "Ok");
+        assert_eq!(send(&mut stream, "PUT (\"a\") ((\"2\"))"), "Ok");
+    }
+
+    // Entries of older versions were written without a sequence number
+    let old: String = read_log(&dir)
+        .iter()
+        .map(|line| {
+            let start = line.find("\"seq\":").unwrap();
+            let end = start + line[start..].find(',').unwrap() + 1;
+            format!("{}{}\n", &line[..start], &line[end..])
+        })
+        .collect();
+    fs::write(dir.join("clavrs.log"), old).unwrap();
+
+    // The snapshot is taken before anything new is logged
+    {
+        let server = Server::with_log(&dir, &args);
+        let mut stream = server.connect();
+        assert_eq!(send(&mut stream, "GET (\"a\")"), "[[\"1\", \"2\"]]");
+        assert_eq!(send(&mut stream, "SNAPSHOT"), "Ok");
+        assert_eq!(send(&mut stream, "PUT (\"b\") ((\"3\"))"), "Ok");
+    }
+
+    let server = Server::with_log(&dir, &args);
+    let mut stream = server.connect();
+    assert_eq!(send(&mut stream, "GET (\"a\", \"b\")"), "[[\"1\", \"2\"], [\"3\"]]");
+}
+
+#[test]
+fn unusable_log_path_exits_with_an_error() {
+    let dir = test_dir("log-path-error");
+
+    // A directory can be opened, but not read as a log
+    let output = Command::new(env!("CARGO_BIN_EXE_clavrs"))
+        .args(["--address", "127.0.0.1:0", "--log-path", dir.to_str().unwrap()])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Could not replay"));
+}
+
+#[test]
+fn rewrite_keeps_writes_made_while_it_runs() {
+    let dir = test_dir("rewrite-concurrent");
+    let log = dir.join("clavrs.log");
+    let mut model: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let keys: Vec<String> = (0..1000).map(|i| format!("k{}", i)).chain((0..1000).map(|i| format!("n{}", i))).collect();
+
+    {
+        let server = Server::with_log(&dir, &[]);
+        let mut stream = server.connect();
+
+        // Every key is written over several times, so the log holds far more entries than keys
+        for round in 0..5 {
+            for i in 0..1000 {
+                assert_eq!(send(&mut stream, &format!("REPLACE \"k{}\" (\"{}\")", i, round)), "Ok");
+                model.insert(format!("k{}", i), vec![round.to_string()]);
+            }
+        }
+        let before = fs::metadata(&log).unwrap().len();
+
+        // Sent right behind REWRITE, so they are applied while the keys are scanned
+        assert_eq!(send(&mut stream, "REWRITE"), "Ok");
+        for i in 0..1000 {
+            let key = format!("k{}", i);
+            let command = match i % 3 {
+                0 => {
+                    model.insert(key.clone(), vec!["during".to_string()]);
+                    format!("REPLACE \"{}\" (\"during\")", key)
+                }
+                1 => {
+                    model.remove(&key);
+                    format!("DELETE (\"{}\")", key)
+                }
+                _ => {
+                    model.insert(format!("n{}", i), vec!["new".to_string()]);
+                    format!("PUT (\"n{}\") ((\"new\"))", i)
+                }
+            };
+            assert_eq!(send(&mut stream, &command), "Ok", "{}", command);
+        }
+        wait_for_rewrite(&mut stream);
+
+        assert_state(&mut stream, &keys, &model);
+        let after = fs::metadata(&log).unwrap().len();
+        assert!(after < before, "Rewritten log has {} bytes, the old one had {}", after, before);
+    }
+
+    // The rewritten log alone gives the same state
+    let server = Server::with_log(&dir, &[]);
+    let mut stream = server.connect();
+    assert_state(&mut stream, &keys, &model);
+}